@@ -1,300 +1,1793 @@
-use crate::parser::Node;
-use std::collections::HashMap;
+use crate::parser::{Node, Span};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+mod bigint;
+mod builtins;
+mod error;
+
+pub use self::bigint::BigInt;
+pub use self::error::{Error, Frame, RuntimeError};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Value {
   String(String),
-  Number(i32),
+  Number(i64),
+  // An integer outside the `i64` range. Results that fit in an `i64` again become a `Number`.
+  BigInt(BigInt),
+  Float(f64),
   Bool(bool),
+  Function(Rc<Closure>),
+  // Lists are shared, so `push(xs, 1)` or `xs[0] = 1` is visible through every reference to the list.
+  List(Rc<RefCell<Vec<Value>>>),
+  // Maps are shared the same way lists are.
+  Map(Rc<RefCell<Map>>),
+  // Struct values are shared too, so `p.x = 3` updates every reference to `p`.
+  Struct(Rc<RefCell<Struct>>),
+  // A value of a user-defined enum, e.g. `Circle(2)`.
+  Variant(Rc<Variant>),
+  // A fixed-size group of values, e.g. `(1, "a")`. Tuples can't be changed once built.
+  Tuple(Vec<Value>),
+  // The value of statements such as `let`, and of functions that don't produce anything.
+  Unit,
+}
+
+impl Value {
+  pub fn list(values: Vec<Value>) -> Value {
+    Value::List(Rc::new(RefCell::new(values)))
+  }
+
+  pub fn map(map: Map) -> Value {
+    Value::Map(Rc::new(RefCell::new(map)))
+  }
+
+  // Build `Some(value)` of the predeclared `Option` enum.
+  pub fn some(value: Value) -> Value {
+    Value::Variant(Rc::new(Variant { enum_name: "Option".to_string(), name: "Some".to_string(), fields: vec![value] }))
+  }
+
+  // Build `None` of the predeclared `Option` enum.
+  pub fn none() -> Value {
+    Value::Variant(Rc::new(Variant { enum_name: "Option".to_string(), name: "None".to_string(), fields: vec![] }))
+  }
+
+  // The name of the value's type, for error messages.
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      Value::String(_) => "string",
+      Value::Number(_) | Value::BigInt(_) => "int",
+      Value::Float(_) => "float",
+      Value::Bool(_) => "bool",
+      Value::Function(_) => "function",
+      Value::List(_) => "list",
+      Value::Map(_) => "map",
+      Value::Struct(_) => "struct",
+      Value::Variant(_) => "enum",
+      Value::Tuple(_) => "tuple",
+      Value::Unit => "unit",
+    }
+  }
+}
+
+// A map from keys to values that remembers insertion order, so iterating over it, printing it
+// and `keys`/`values` always list the entries the same way.
+#[derive(Debug, Clone, Default)]
+pub struct Map {
+  entries: Vec<(Value, Value)>,
+}
+
+impl Map {
+  pub fn new() -> Map {
+    Map { entries: Vec::new() }
+  }
+
+  pub fn get(&self, key: &Value) -> Option<&Value> {
+    self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+  }
+
+  // Insert or replace the value stored under `key`. Only strings, integers and booleans can be keys.
+  pub fn insert(&mut self, key: Value, value: Value) -> Result<(), RuntimeError> {
+    match key {
+      Value::String(_) | Value::Number(_) | Value::BigInt(_) | Value::Bool(_) => (),
+      _ => return Err(RuntimeError::InvalidMapKey { found: key.type_name() }),
+    }
+    match self.entries.iter_mut().find(|(k, _)| *k == key) {
+      Some(entry) => entry.1 = value,
+      None => self.entries.push((key, value)),
+    }
+    Ok(())
+  }
+
+  pub fn remove(&mut self, key: &Value) -> Option<Value> {
+    let ix = self.entries.iter().position(|(k, _)| k == key)?;
+    Some(self.entries.remove(ix).1)
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  pub fn keys(&self) -> Vec<Value> {
+    self.entries.iter().map(|(k, _)| k.clone()).collect()
+  }
+
+  pub fn values(&self) -> Vec<Value> {
+    self.entries.iter().map(|(_, v)| v.clone()).collect()
+  }
+}
+
+// Two maps are equal when they hold the same entries, whatever order they were inserted in.
+impl PartialEq for Map {
+  fn eq(&self, other: &Map) -> bool {
+    self.len() == other.len() && self.entries.iter().all(|(k, v)| other.get(k) == Some(v))
+  }
+}
+
+// An instance of a user-defined struct. Fields are kept in declaration order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Struct {
+  pub name: String,
+  pub fields: Vec<(String, Value)>,
+}
+
+impl Struct {
+  pub fn get(&self, field: &str) -> Option<&Value> {
+    self.fields.iter().find(|(name, _)| name == field).map(|(_, value)| value)
+  }
+
+  pub fn get_mut(&mut self, field: &str) -> Option<&mut Value> {
+    self.fields.iter_mut().find(|(name, _)| name == field).map(|(_, value)| value)
+  }
+}
+
+// An instance of one variant of a user-defined enum.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+  pub enum_name: String,
+  pub name: String,
+  pub fields: Vec<Value>,
+}
+
+// Format a value nested inside a list or map. Strings are quoted so `["1"]` and `[1]` print differently.
+fn fmt_nested(value: &Value, f: &mut fmt::Formatter) -> fmt::Result {
+  match value {
+    Value::String(value) => write!(f, "{:?}", value),
+    _ => write!(f, "{}", value),
+  }
+}
+
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Value::String(value) => write!(f, "{}", value),
+      Value::Number(value) => write!(f, "{}", value),
+      Value::BigInt(value) => write!(f, "{}", value),
+      // Debug formatting keeps the decimal point on whole numbers, so `2.0` doesn't print like the integer `2`.
+      Value::Float(value) => write!(f, "{:?}", value),
+      Value::Bool(value) => write!(f, "{}", value),
+      Value::Function(closure) => write!(f, "<fn {}>", closure.name),
+      Value::List(values) => {
+        write!(f, "[")?;
+        for (ix, value) in values.borrow().iter().enumerate() {
+          if ix > 0 {
+            write!(f, ", ")?;
+          }
+          fmt_nested(value, f)?;
+        }
+        write!(f, "]")
+      },
+      Value::Map(map) => {
+        write!(f, "{{")?;
+        for (ix, (key, value)) in map.borrow().entries.iter().enumerate() {
+          if ix > 0 {
+            write!(f, ", ")?;
+          }
+          fmt_nested(key, f)?;
+          write!(f, ": ")?;
+          fmt_nested(value, f)?;
+        }
+        write!(f, "}}")
+      },
+      Value::Struct(value) => {
+        let value = value.borrow();
+        write!(f, "{} {{", value.name)?;
+        for (ix, (name, field)) in value.fields.iter().enumerate() {
+          if ix > 0 {
+            write!(f, ",")?;
+          }
+          write!(f, " {}: ", name)?;
+          fmt_nested(field, f)?;
+        }
+        write!(f, " }}")
+      },
+      Value::Tuple(values) => {
+        write!(f, "(")?;
+        for (ix, value) in values.iter().enumerate() {
+          if ix > 0 {
+            write!(f, ", ")?;
+          }
+          fmt_nested(value, f)?;
+        }
+        // A one-element tuple keeps its trailing comma, like the literal.
+        if values.len() == 1 {
+          write!(f, ",")?;
+        }
+        write!(f, ")")
+      },
+      Value::Variant(variant) => {
+        write!(f, "{}", variant.name)?;
+        if !variant.fields.is_empty() {
+          write!(f, "(")?;
+          for (ix, field) in variant.fields.iter().enumerate() {
+            if ix > 0 {
+              write!(f, ", ")?;
+            }
+            fmt_nested(field, f)?;
+          }
+          write!(f, ")")?;
+        }
+        Ok(())
+      },
+      Value::Unit => write!(f, "()"),
+    }
+  }
 }
 
+// A function value. `body` has the same shape as the entries of `Runtime.functions`: an optional
+// `FunctionArguments` node naming the parameters, followed by the statements. It is shared, so calling a
+// function doesn't copy its syntax tree. `env` is the local scope the function was created in, if it was created
+// inside another function. It is shared rather than copied, so the function sees variables defined there after
+// it was created, including the variable it is stored in, and assignments to them from either side are seen by
+// both, even after the function that created the scope has returned.
+pub struct Closure {
+  pub name: String,
+  pub body: Rc<Vec<Node>>,
+  pub env: Option<Rc<Scope>>,
+}
 
-struct Runtime {
-  functions: HashMap<String, Vec<Node>>,
-  stack: Vec<HashMap<String, Value>>,
+// A captured scope can hold the function value that captured it, so only the name is printed.
+impl fmt::Debug for Closure {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Closure").field("name", &self.name).finish_non_exhaustive()
+  }
+}
+
+// Two function values are only equal if they are the same function.
+impl PartialEq for Closure {
+  fn eq(&self, other: &Closure) -> bool {
+    std::ptr::eq(self, other)
+  }
+}
+
+// The variables of a running function, or the globals. A function value created inside another function looks up
+// names in its own scope first, then in the scope it was created in and that scope's `parent`s, then in the globals.
+#[derive(Default)]
+pub struct Scope {
+  variables: RefCell<HashMap<String, Value>>,
+  parent: Option<Rc<Scope>>,
+}
+
+// How deeply script function calls may nest before a call fails with `StackOverflow`. Each nested call takes
+// roughly 6 KiB of host stack in a debug build and 2 KiB in a release build, so plain recursion reaches this limit
+// well within `DEFAULT_MAX_STACK_SIZE`; calls nested inside heavier expressions can run out of that budget first.
+// Scripts on a bigger thread can raise both with `with_max_call_depth` and `with_max_stack_size`. Calls made by
+// `return f(...)` reuse the running call and don't count, so they never reach this limit, and recursion through
+// them that never ends runs forever unless `with_max_steps` sets a step limit.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 100;
+
+// How much of the host thread's stack the function calls of a script may use before a call fails with
+// `StackExhausted`, whatever their depth. It must be less than the thread's stack size; threads started with
+// `std::thread::spawn` get 2 MiB.
+pub const DEFAULT_MAX_STACK_SIZE: usize = 1024 * 1024;
+
+pub struct Runtime {
+  functions: HashMap<String, Rc<Vec<Node>>>,
+  // Field names of each declared struct, in declaration order.
+  structs: HashMap<String, Vec<String>>,
+  // Variant names of each declared enum, and the enum and number of fields of each variant.
+  enums: HashMap<String, Vec<String>>,
+  variants: HashMap<String, (String, usize)>,
+  stack: Vec<Rc<Scope>>,
+  // Set by a `return` statement so enclosing loops stop, and cleared once the function call completes.
+  returning: bool,
+  // The user functions currently running, outermost first, used to build backtraces.
+  calls: Vec<Call>,
+  max_call_depth: usize,
+  max_stack_size: usize,
+  // Where the host stack was when the outermost function call started.
+  stack_base: usize,
+  // How many nodes the script has evaluated, and how many it may evaluate before it is stopped.
+  steps: usize,
+  max_steps: usize,
+  // A call made by a `return` statement, waiting for the function that made it to finish.
+  tail_call: Option<TailCall>,
+}
+
+// A running function and the call that started it. `main`, started by the interpreter itself, has no call site.
+struct Call {
+  name: String,
+  site: Option<Span>,
+  // How many `try` bodies the function is inside. Calls there can't be tail calls, since the `catch` needs them
+  // to finish first.
+  trying: usize,
+  // If the function was started by a tail call, the function that made it and where, and how many functions
+  // before that were replaced by tail calls too.
+  tail_caller: Option<Frame>,
+}
+
+// A call in tail position, with its arguments already bound. It takes over the frame and call depth of the
+// function that made it.
+struct TailCall {
+  name: String,
+  body: Rc<Vec<Node>>,
+  frame: Rc<Scope>,
+  site: Span,
+}
+
+// What a call by name resolves to: a script function to run with its arguments, or the value of a variant
+// constructor or builtin, which is computed on the spot.
+enum Callee {
+  Function(Rc<Closure>, Vec<Argument>),
+  Value(Value),
+}
+
+impl Default for Runtime {
+  fn default() -> Runtime {
+    Runtime::new()
+  }
 }
 
 impl Runtime {
 
   pub fn new() -> Runtime {
-    Runtime {
+    let mut runtime = Runtime {
       functions: HashMap::new(),
-      stack: Vec::new(),
+      structs: HashMap::new(),
+      enums: HashMap::new(),
+      variants: HashMap::new(),
+      // The bottom frame holds the global variables defined by top-level statements.
+      stack: vec![Rc::default()],
+      returning: false,
+      calls: vec![],
+      max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+      max_stack_size: DEFAULT_MAX_STACK_SIZE,
+      stack_base: 0,
+      steps: 0,
+      max_steps: usize::MAX,
+      tail_call: None,
+    };
+    // Every script can use `enum Option { Some(value), None }` for values that may be missing.
+    runtime.enums.insert("Option".to_string(), vec!["Some".to_string(), "None".to_string()]);
+    runtime.variants.insert("Some".to_string(), ("Option".to_string(), 1));
+    runtime.variants.insert("None".to_string(), ("Option".to_string(), 0));
+    runtime
+  }
+
+  // Set how deeply function calls may nest, in place of `DEFAULT_MAX_CALL_DEPTH`.
+  pub fn with_max_call_depth(mut self, depth: usize) -> Runtime {
+    self.max_call_depth = depth;
+    self
+  }
+
+  // Set how many bytes of host stack function calls may use, in place of `DEFAULT_MAX_STACK_SIZE`. A script run on
+  // a thread with a bigger stack can recurse more deeply with a bigger limit.
+  pub fn with_max_stack_size(mut self, bytes: usize) -> Runtime {
+    self.max_stack_size = bytes;
+    self
+  }
+
+  // Stop a script with `StepLimitExceeded` once it has evaluated `steps` nodes. There is no limit by default, so a
+  // script that never finishes, such as `fn f(n) { return f(n); }`, runs until it is killed.
+  pub fn with_max_steps(mut self, steps: usize) -> Runtime {
+    self.max_steps = steps;
+    self
+  }
+
+  // Run a program, then its `main` function if it declares one.
+  pub fn start(&mut self, node: &Node) -> Result<Value, Error> {
+    let result = self.run(node).map_err(|error| *error)?;
+    if !self.functions.contains_key("main") {
+      return Ok(result);
     }
+    self.call_function("main", &[], None).map_err(|error| *error)
   }
 
-  // Define the `run` method of the `Runtime` struct.
-  pub fn run(&mut self, node: &Node) -> Result<Value, &'static str> {
+  // Evaluate a node. `run` is on the host stack once for every level of nesting in a running script, so it only
+  // dispatches: each kind of node with work to do has its own method, which keeps this stack frame small.
+  pub fn run(&mut self, node: &Node) -> Result<Value, Box<Error>> {
+    self.steps += 1;
+    if self.steps > self.max_steps {
+      return Err(RuntimeError::StepLimitExceeded { limit: self.max_steps }.into());
+    }
+    // An `Expression` only wraps the node that computes its value. It is unwrapped here rather than run by
+    // another call to `run`, which would take another stack frame.
+    let node = match node {
+      Node::Expression { children } => {
+        match children[0] {
+          Node::MathExpression { .. } |
+          Node::UnaryExpression { .. } |
+          Node::IfExpression { .. } |
+          Node::ComparisonExpression { .. } |
+          Node::Number { .. } |
+          Node::BigNumber { .. } |
+          Node::Float { .. } |
+          Node::FunctionCall { .. } |
+          Node::Call { .. } |
+          Node::FunctionLiteral { .. } |
+          Node::List { .. } |
+          Node::Map { .. } |
+          Node::Tuple { .. } |
+          Node::Index { .. } |
+          Node::StructLiteral { .. } |
+          Node::FieldAccess { .. } |
+          Node::MatchExpression { .. } |
+          Node::String { .. } |
+          Node::Interpolation { .. } |
+          Node::Bool { .. } |
+          Node::Identifier { .. } => &children[0],
+          _ => return Err(RuntimeError::InvalidNode { node: "expression" }.into()),
+        }
+      },
+      _ => node,
+    };
     // Match the type of the input `Node`.
     match node {
-        // If the `Node` is a `Program`, evaluate each of its children in sequence.
-        Node::Program { children } => {
-            for n in children {
-                match n {
-                    // If the child node is a `FunctionDefine`, add it to the list of functions.
-                    Node::FunctionDefine { .. } => {
-                        self.run(n);
-                    },
-                    // If the child node is an `Expression`, add it as the body of a new `main` function.
-                    Node::Expression { .. } => {
-                        self.functions.insert("main".to_string(), vec![Node::FunctionReturn { children: vec![n.clone()] }]);
-                    },
-                    // If the child node is a `Statement`, add it as the body of a new `main` function.
-                    Node::Statement { .. } => {
-                        self.functions.insert("main".to_string(), vec![n.clone()]);
-                    }
-                    // Ignore any other type of child node.
-                    _ => (),
-                }
-            }
-            // Return `Value::Bool(true)` wrapped in a `Result`.
-            Ok(Value::Bool(true))
-        },
-        // If the `Node` is a `MathExpression`, evaluate it.
-        Node::MathExpression { name, children } => {
-            // Evaluate the left and right children of the `MathExpression`.
-            match (self.run(&children[0]), self.run(&children[1])) {
-                // If both children are `Number` values, extract their values and evaluate the expression.
-                (Ok(Value::Number(lhs)), Ok(Value::Number(rhs))) => {
-                    match name.as_ref() {
-                        // If the operator is `+`, add the values.
-                        "+" => Ok(Value::Number(lhs + rhs)),
-                        // If the operator is `-`, subtract the values.
-                        "-" => Ok(Value::Number(lhs - rhs)),
-                        // If the operator is `*`, multiply the values.
-                        "*" => Ok(Value::Number(lhs * rhs)),
-                        // If the operator is `/`, divide the values.
-                        "/" => Ok(Value::Number(lhs / rhs)),
-                        // If the operator is `^`, raise the left value to the power of the right value.
-                        "^" => {
-                            let mut result = 1;
-                            for i in 0..rhs {
-                                result = result * lhs;
-                            }
-                            Ok(Value::Number(result))
-                        },
-                        // If the operator is not recognized, return an error message.
-                        _ => Err("Undefined operator"),
-                    }
-                }
-                // If either child is not a `Number` value, return an error message.
-                _ => Err("Invalid"),
-            }
+      Node::Program { children } => self.run_program(node, children),
+      Node::MathExpression { name, children } => self.run_math_expression(name, children),
+      Node::UnaryExpression { name, children } => self.run_unary_expression(name, children),
+      Node::ComparisonExpression { name, children } => self.run_comparison_expression(name, children),
+      Node::IfExpression { children } => self.run_if_expression(children),
+      Node::FunctionCall { name, children, span } => self.run_function_call(name, children, *span),
+      Node::Call { children, span } => self.run_call(children, *span),
+      Node::List { children } => self.run_list(children),
+      Node::Map { children } => self.run_map(children),
+      Node::StructDefine { name, children } => self.run_struct_define(name, children),
+      Node::StructLiteral { name, children } => self.run_struct_literal(name, children),
+      Node::Tuple { children } => self.run_tuple(children),
+      Node::FieldAccess { name, children } => self.run_field_access(name, children),
+      Node::EnumDefine { name, children } => self.run_enum_define(name, children),
+      Node::MatchExpression { children } => self.run_match_expression(children),
+      Node::ForLoop { children } => self.run_for_loop(children),
+      Node::TryCatch { children } => self.run_try_catch(children),
+      Node::Block { children } => self.run_block(children),
+      Node::Throw { children } => self.run_throw(children),
+      Node::Index { children } => self.run_index(children),
+      Node::Assignment { children } => self.run_assignment(children),
+      Node::FunctionLiteral { children } => self.run_function_literal(children),
+      Node::FunctionDefine { children } => self.run_function_define(children),
+      // If the `Node` is a `FunctionReturn`, evaluate its child node.
+      Node::FunctionReturn { children } => {
+        self.run(&children[0])
+      },
+      Node::Identifier { value } => self.run_identifier(value),
+      Node::Statement { children, span } => self.run_statement(children, *span),
+      Node::VariableDefine { children } => self.run_variable_define(children),
+      // If the `Node` is a `Number`, wrap its value in a `Value::Number` and return it.
+      Node::Number { value } => {
+        Ok(Value::Number(*value))
+      },
+      Node::BigNumber { value } => self.run_big_number(value),
+      // If the `Node` is a `Float`, wrap its value in a `Value::Float` and return it.
+      Node::Float { value } => {
+        Ok(Value::Float(*value))
+      },
+      // If the `Node` is a `String`, wrap its value in a `Value::String` and return it.
+      Node::String { value } => {
+        Ok(Value::String(value.clone()))
+      },
+      Node::Interpolation { children } => self.run_interpolation(children),
+      // If the `Node` is a `Bool`, wrap its value in a `Value::Bool` and return it.
+      Node::Bool { value } => {
+        Ok(Value::Bool(*value))
+      },
+      // If the `Node` is of an unhandled type, return an error message.
+      _ => {
+        Err(RuntimeError::InvalidNode { node: "node" }.into())
+      },
+    }
+  }
+
+  // If the `Node` is a `Program`, run it as a script.
+  fn run_program(&mut self, node: &Node, children: &[Node]) -> Result<Value, Box<Error>> {
+    // Register every function, struct and enum first so top-level code can use ones defined further down.
+    for n in children {
+      if let Node::FunctionDefine { .. } | Node::StructDefine { .. } | Node::EnumDefine { .. } = n {
+        self.run(n)?;
+      }
+    }
+    // Report mistakes that can be found without running anything before the script starts.
+    let mut variables = HashSet::new();
+    bound_names(node, &mut variables);
+    self.check(node, &variables)?;
+    // Execute the remaining top-level statements in order, in the global frame.
+    let mut result = Value::Unit;
+    for n in children {
+      match n {
+        Node::FunctionDefine { .. } |
+        Node::StructDefine { .. } |
+        Node::EnumDefine { .. } => (),
+        Node::Statement { .. } |
+        Node::Expression { .. } => {
+          result = self.run(n)?;
+          // A top-level `return` ends the script.
+          if self.returning {
+            break;
+          }
         },
-        // Cut 1: comparison operators
-        // If the `Node` is a `ComparisonExpression`, evaluate it.
-        Node::ComparisonExpression { name, children } => {
-            let lhs = self.run(&children[0]);
-            let rhs = self.run(&children[1]);
-            // Evaluate the left and right children of the `Conditional expression`.
-            match (lhs, rhs) {
-                // If both children are `Number` values, extract their values and evaluate the expression.
-                (Ok(Value::Number(lhs)), Ok(Value::Number(rhs))) => {
-                    match name.as_ref() {
-                        // If the operator is `>`, check if left side is greater than the right side, then return true or false
-                        ">" => { if lhs > rhs {Ok(Value::Bool(true))} else {Ok(Value::Bool(false))} },
-                        "<" => { if lhs < rhs {Ok(Value::Bool(true))} else {Ok(Value::Bool(false))}},
-                        ">=" => { if lhs >= rhs {Ok(Value::Bool(true))} else {Ok(Value::Bool(false))}},
-                        "<=" => { if lhs <= rhs {Ok(Value::Bool(true))} else {Ok(Value::Bool(false))}},
-                        "==" => { if lhs == rhs {Ok(Value::Bool(true))} else {Ok(Value::Bool(false))}},
-                        "!=" => { if lhs != rhs {Ok(Value::Bool(true))} else {Ok(Value::Bool(false))}},
-                        // If the operator is not recognized, return an error message.
-                        _ => Err("Invalid comparison operator"),
-                    }
-                }
-                // if lhs and rhs are both boolean, match their equality or inequality
-                (Ok(Value::Bool(lhs)), Ok(Value::Bool(rhs))) => {
-                    match name.as_ref() {
-                        "==" => { if lhs == rhs { Ok(Value::Bool(true)) } else { Ok(Value::Bool(false)) } },
-                        "!=" => { if lhs != rhs { Ok(Value::Bool(true)) } else { Ok(Value::Bool(false)) } },
-                        _ => Err("Invalid comparison expression"),
-                    }
-                }
-                _ => Err("Invalid comparison expression"),
+        // Ignore any other type of child node.
+        _ => (),
+      }
+    }
+    // The `return` only ends the top-level statements, so `main` still runs in full.
+    self.returning = false;
+    // Return the value of the last top-level statement.
+    Ok(result)
+  }
+
+  // If the `Node` is a `MathExpression`, evaluate it.
+  fn run_math_expression(&mut self, name: &str, children: &[Node]) -> Result<Value, Box<Error>> {
+    // Evaluate the left and right children of the `MathExpression`.
+    let lhs = self.run(&children[0])?;
+    let rhs = self.run(&children[1])?;
+    Ok(math(name, lhs, rhs)?)
+  }
+
+  // If the `Node` is a `UnaryExpression`, evaluate its operand and apply the operator.
+  fn run_unary_expression(&mut self, name: &str, children: &[Node]) -> Result<Value, Box<Error>> {
+    match (name, self.run(&children[0])?) {
+      // If the operator is `~`, flip every bit, so `~n` is `-n - 1`.
+      ("~", Value::Number(value)) => Ok(Value::Number(!value)),
+      ("~", Value::BigInt(_)) => Err(RuntimeError::IntegerTooLarge { op: name.to_string() }.into()),
+      (_, value) => Err(RuntimeError::InvalidOperand { op: name.to_string(), found: value.type_name() }.into()),
+    }
+  }
+
+  // Cut 1: comparison operators
+  // If the `Node` is a `ComparisonExpression`, evaluate it.
+  fn run_comparison_expression(&mut self, name: &str, children: &[Node]) -> Result<Value, Box<Error>> {
+    let lhs = self.run(&children[0])?;
+    let rhs = self.run(&children[1])?;
+    Ok(comparison(name, lhs, rhs)?)
+  }
+
+  // Cut 2: if expression
+  fn run_if_expression(&mut self, children: &[Node]) -> Result<Value, Box<Error>> {
+    // Match first children - if branch
+    match &children[0] {
+      Node::IfBranch { children } => {
+        match self.run(&children[0])? { // match the condition in if branch
+          Value::Bool(true) => { // if true, we return the value inside
+            return self.run(&children[1]);
+          },
+          Value::Bool(false) => (), // else, we skip to the next branch
+          value => return Err(RuntimeError::ExpectedBool { found: value.type_name() }.into()), // error
+        }
+      },
+      _ => return Err(RuntimeError::InvalidNode { node: "if expression" }.into()),
+    }
+    if children.len() > 2 { // Check if children has 3 elements or more, this indicates that there is an else if branch
+      if let Node::ElifBranch { children } = &children[1] { // match the elif branch in the 2nd index or 1
+        match self.run(&children[0])? { // match condition in else if branch
+          Value::Bool(true) => { // if true, we return value inside
+            return self.run(&children[1]);
+          },
+          Value::Bool(false) => (), // else we move on to else branch
+          value => return Err(RuntimeError::ExpectedBool { found: value.type_name() }.into()),
+        }
+        if let Node::ElseBranch { children } = &children[2] { // if elif branch fails, we run else branch
+          return self.run(&children[0]);
+        }
+      }
+    }        
+    else if let Node::ElseBranch { children } = &children[1] { // children length is less than 3, then we only have if and else branch
+      return self.run(&children[0]); // run value in else branch if if_branch fails
+    }
+    Err(RuntimeError::InvalidNode { node: "if expression" }.into())
+  }
+
+  // If the `Node` is a `FunctionCall`, evaluate it. Errors inside the call point at the call unless they
+  // already know a more precise location.
+  fn run_function_call(&mut self, name: &str, children: &[Node], span: Span) -> Result<Value, Box<Error>> {
+    let result = self.call_function(name, children, Some(span));
+    result.map_err(|error| self.locate(error, span))
+  }
+
+  // If the `Node` is a `Call`, evaluate the callee and call the function value it produces.
+  fn run_call(&mut self, children: &[Node], span: Span) -> Result<Value, Box<Error>> {
+    let callee = self.run(&children[0])?;
+    let args = self.eval_arguments(&children[1..])?;
+    let result = match callee {
+      Value::Function(closure) => self.call_closure(&closure, args, Some(span)),
+      _ => Err(RuntimeError::NotAFunction { found: callee.type_name() }.into()),
+    };
+    result.map_err(|error| self.locate(error, span))
+  }
+
+  // If the `Node` is a `List`, evaluate each element in order.
+  fn run_list(&mut self, children: &[Node]) -> Result<Value, Box<Error>> {
+    let mut values = vec![];
+    for n in children {
+      values.push(self.run(n)?);
+    }
+    Ok(Value::list(values))
+  }
+
+  // If the `Node` is a `Map`, evaluate each key and value in order.
+  fn run_map(&mut self, children: &[Node]) -> Result<Value, Box<Error>> {
+    let mut map = Map::new();
+    for entry in children {
+      if let Node::MapEntry { children } = entry {
+        let key = self.run(&children[0])?;
+        let value = self.run(&children[1])?;
+        map.insert(key, value)?;
+      }
+    }
+    Ok(Value::map(map))
+  }
+
+  // If the `Node` is a `StructDefine`, record the struct's field names.
+  fn run_struct_define(&mut self, name: &str, children: &[Node]) -> Result<Value, Box<Error>> {
+    let mut fields = vec![];
+    for field in children {
+      if let Node::Identifier { value } = field {
+        fields.push(value.clone());
+      }
+    }
+    self.structs.insert(name.to_string(), fields);
+    Ok(Value::Unit)
+  }
+
+  // If the `Node` is a `StructLiteral`, build a struct value with exactly the declared fields.
+  fn run_struct_literal(&mut self, name: &str, children: &[Node]) -> Result<Value, Box<Error>> {
+    let declared = match self.structs.get(name) {
+      Some(fields) => fields.clone(),
+      None => return Err(RuntimeError::UndefinedStruct { name: name.to_string() }.into()),
+    };
+    let mut values = HashMap::new();
+    for init in children {
+      if let Node::FieldInit { name, children } = init {
+        if !declared.contains(name) {
+          return Err(RuntimeError::UnknownField { name: name.to_string() }.into());
+        }
+        let value = self.run(&children[0])?;
+        values.insert(name.to_string(), value);
+      }
+    }
+    let mut fields = vec![];
+    for field in declared {
+      match values.remove(&field) {
+        Some(value) => fields.push((field, value)),
+        None => return Err(RuntimeError::MissingField { name: field }.into()),
+      }
+    }
+    Ok(Value::Struct(Rc::new(RefCell::new(Struct { name: name.to_string(), fields }))))
+  }
+
+  // If the `Node` is a `Tuple`, evaluate each element in order.
+  fn run_tuple(&mut self, children: &[Node]) -> Result<Value, Box<Error>> {
+    let mut values = vec![];
+    for n in children {
+      values.push(self.run(n)?);
+    }
+    Ok(Value::Tuple(values))
+  }
+
+  // If the `Node` is a `FieldAccess`, read the field from a struct value, or the element of a tuple.
+  fn run_field_access(&mut self, name: &str, children: &[Node]) -> Result<Value, Box<Error>> {
+    match self.run(&children[0])? {
+      Value::Struct(target) => {
+        match target.borrow().get(name) {
+          Some(value) => Ok(value.clone()),
+          None => Err(RuntimeError::UnknownField { name: name.to_string() }.into()),
+        }
+      },
+      Value::Tuple(values) => {
+        match name.parse::<usize>() {
+          Ok(ix) if ix < values.len() => Ok(values[ix].clone()),
+          Ok(ix) => Err(RuntimeError::IndexOutOfBounds { index: ix as i64, len: values.len() }.into()),
+          Err(_) => Err(RuntimeError::InvalidFieldAccess { field: name.to_string(), found: "tuple" }.into()),
+        }
+      },
+      value => Err(RuntimeError::InvalidFieldAccess { field: name.to_string(), found: value.type_name() }.into()),
+    }
+  }
+
+  // If the `Node` is an `EnumDefine`, record its variants.
+  fn run_enum_define(&mut self, name: &str, children: &[Node]) -> Result<Value, Box<Error>> {
+    let mut variants = vec![];
+    for variant in children {
+      if let Node::EnumVariant { name: variant, children } = variant {
+        self.variants.insert(variant.clone(), (name.to_string(), children.len()));
+        variants.push(variant.clone());
+      }
+    }
+    self.enums.insert(name.to_string(), variants);
+    Ok(Value::Unit)
+  }
+
+  // If the `Node` is a `MatchExpression`, evaluate the body of the first arm whose pattern matches.
+  fn run_match_expression(&mut self, children: &[Node]) -> Result<Value, Box<Error>> {
+    let body = self.match_arm(children)?;
+    self.run(body)
+  }
+
+  // If the `Node` is a `ForLoop`, run the body once per list element or map key.
+  fn run_for_loop(&mut self, children: &[Node]) -> Result<Value, Box<Error>> {
+    let name = match &children[0] {
+      Node::Identifier { value } => value.clone(),
+      _ => return Err(RuntimeError::InvalidNode { node: "for loop" }.into()),
+    };
+    // Iterate over a snapshot, so the body can change the collection without affecting the loop.
+    let items = match self.run(&children[1])? {
+      Value::List(values) => values.borrow().clone(),
+      Value::Map(map) => map.borrow().keys(),
+      value => return Err(RuntimeError::NotIterable { found: value.type_name() }.into()),
+    };
+    // A loop has no value of its own, unless a `return` inside it ends the function.
+    let mut result = Value::Unit;
+    'items: for item in items {
+      self.locals().insert(name.to_string(), item);
+      for n in &children[2..] {
+        let value = self.run(n)?;
+        if self.returning {
+          result = value;
+          break 'items;
+        }
+      }
+    }
+    Ok(result)
+  }
+
+  // If the `Node` is a `TryCatch`, run the body, and if it fails run the handler with the error bound to
+  // its variable. A thrown value is bound as it is; a runtime error is bound as its message.
+  fn run_try_catch(&mut self, children: &[Node]) -> Result<Value, Box<Error>> {
+    let name = match &children[1] {
+      Node::Identifier { value } => value.clone(),
+      _ => return Err(RuntimeError::InvalidNode { node: "try" }.into()),
+    };
+    if let Some(call) = self.calls.last_mut() {
+      call.trying += 1;
+    }
+    let result = self.run(&children[0]);
+    if let Some(call) = self.calls.last_mut() {
+      call.trying -= 1;
+    }
+    match result {
+      Ok(value) => Ok(value),
+      Err(error) => {
+        let caught = match error.error {
+          RuntimeError::Thrown { value } => value,
+          error => Value::String(error.to_string()),
+        };
+        self.locals().insert(name, caught);
+        self.run(&children[2])
+      },
+    }
+  }
+
+  // If the `Node` is a `Block`, run its statements in the current frame. Its value is that of the last
+  // statement, or of a `return` inside it.
+  fn run_block(&mut self, children: &[Node]) -> Result<Value, Box<Error>> {
+    let mut result = Value::Unit;
+    for n in children {
+      result = self.run(n)?;
+      if self.returning {
+        break;
+      }
+    }
+    Ok(result)
+  }
+
+  // If the `Node` is a `Throw`, fail with the value, so the nearest enclosing `try` can catch it.
+  fn run_throw(&mut self, children: &[Node]) -> Result<Value, Box<Error>> {
+    let value = self.run(&children[0])?;
+    Err(RuntimeError::Thrown { value }.into())
+  }
+
+  // If the `Node` is an `Index`, look up the element at that position.
+  fn run_index(&mut self, children: &[Node]) -> Result<Value, Box<Error>> {
+    let target = self.run(&children[0])?;
+    let index = self.run(&children[1])?;
+    match (target, index) {
+      (Value::List(values), index) => {
+        let values = values.borrow();
+        let ix = index_position(index, values.len())?;
+        Ok(values[ix].clone())
+      },
+      (Value::Map(map), key) => {
+        match map.borrow().get(&key) {
+          Some(value) => Ok(value.clone()),
+          None => Err(RuntimeError::KeyNotFound { key: key.to_string() }.into()),
+        }
+      },
+      (target, _) => Err(RuntimeError::NotIndexable { found: target.type_name() }.into()),
+    }
+  }
+
+  // If the `Node` is an `Assignment`, update an existing variable or list element.
+  fn run_assignment(&mut self, children: &[Node]) -> Result<Value, Box<Error>> {
+    let value = self.run(&children[1])?;
+    match &children[0] {
+      Node::Identifier { value: name } => {
+        // Assign to the innermost scope that already defines the variable.
+        match self.scope_of(name) {
+          Some(scope) => scope.variables.borrow_mut().insert(name.to_string(), value.clone()),
+          None => return Err(RuntimeError::UndefinedVariable { name: name.to_string() }.into()),
+        };
+      },
+      Node::Index { children } => {
+        let target = self.run(&children[0])?;
+        let index = self.run(&children[1])?;
+        match (target, index) {
+          (Value::List(values), index) => {
+            let mut values = values.borrow_mut();
+            let ix = index_position(index, values.len())?;
+            values[ix] = value.clone();
+          },
+          (Value::Map(map), key) => {
+            map.borrow_mut().insert(key, value.clone())?;
+          },
+          (target, _) => return Err(RuntimeError::NotIndexable { found: target.type_name() }.into()),
+        }
+      },
+      Node::FieldAccess { name, children } => {
+        match self.run(&children[0])? {
+          Value::Struct(target) => {
+            match target.borrow_mut().get_mut(name) {
+              Some(slot) => *slot = value.clone(),
+              None => return Err(RuntimeError::UnknownField { name: name.to_string() }.into()),
             }
-        },
-        // Cut 2: if expression
-        Node::IfExpression { children } => {
-            // Match first children - if branch
-            match &children[0] {
-                Node::IfBranch { children } => {
-                    match self.run(&children[0]) { // match the condition in if branch
-                        Ok(Value::Bool(true)) => { // if true, we return the value inside
-                            return self.run(&children[1]);
-                        },
-                        Ok(Value::Bool(false)) => (), // else, we skip to the next branch
-                        _ => return Err("Invalid if expression"), // error
-                    }
-                },
-                _ => return Err("Invalid if expression"),
+          },
+          value => return Err(RuntimeError::InvalidFieldAccess { field: name.to_string(), found: value.type_name() }.into()),
+        }
+      },
+      _ => return Err(RuntimeError::InvalidAssignmentTarget.into()),
+    }
+    Ok(Value::Unit)
+  }
+
+  // If the `Node` is a `FunctionLiteral`, capture the current local scope into a new function value.
+  fn run_function_literal(&mut self, children: &[Node]) -> Result<Value, Box<Error>> {
+    // Globals are visible from every function already, so only local scopes are captured.
+    let env = if self.stack.len() > 1 {
+      self.stack.last().cloned()
+    } else {
+      None
+    };
+    Ok(Value::Function(Rc::new(Closure { name: "<anonymous>".to_string(), body: Rc::new(children.to_vec()), env })))
+  }
+
+  // If the `Node` is a `FunctionDefine`, add it to the list of functions.
+  fn run_function_define(&mut self, children: &[Node]) -> Result<Value, Box<Error>> {
+    let (head, tail) = children.split_at(1);
+    if let Node::Identifier { value } = &head[0] {
+      self.functions.insert(value.to_string(), Rc::new(tail.to_vec()));
+    }
+    Ok(Value::Unit)
+  }
+
+  // If the `Node` is an `Identifier`, look up its value in the current frame, then in the global frame.
+  // A named function used as a value evaluates to a function value.
+  fn run_identifier(&mut self, value: &str) -> Result<Value, Box<Error>> {
+    if let Some(id_value) = self.lookup(value) {
+      return Ok(id_value);
+    }
+    match self.functions.get(value) {
+      Some(statements) => Ok(Value::Function(Rc::new(Closure { name: value.to_string(), body: Rc::clone(statements), env: None }))),
+      // A variant without fields, such as `Red`, is a value on its own.
+      None if self.variants.contains_key(value) => Ok(self.construct_variant(value, vec![])?),
+      None => Err(RuntimeError::UndefinedVariable { name: value.to_string() }.into()),
+    }
+  }
+
+  // If the `Node` is a `Statement`, evaluate its child node.
+  fn run_statement(&mut self, children: &[Node], span: Span) -> Result<Value, Box<Error>> {
+    // Errors without a more precise location point at the statement that raised them.
+    let result = match children[0] {
+      // A `return` statement also stops the rest of the function body from running.
+      Node::FunctionReturn { ref children } => {
+        self.run_tail(&children[0]).inspect(|_| self.returning = true)
+      },
+      Node::VariableDefine { .. } |
+      Node::Throw { .. } |
+      Node::ForLoop { .. } |
+      Node::TryCatch { .. } |
+      Node::Assignment { .. } |
+      Node::Expression { .. } => {
+        self.run(&children[0])
+      },
+      _ => Err(RuntimeError::InvalidNode { node: "statement" }.into()),
+    };
+    result.map_err(|error| self.locate(error, span))
+  }
+
+  // If the `Node` is a `VariableDefine`, evaluate its expression and bind the result to a new variable.
+  fn run_variable_define(&mut self, children: &[Node]) -> Result<Value, Box<Error>> {
+    // Evaluate the expression.
+    let value = self.run(&children[1])?;
+    // Bind the variable, or every name in a destructuring pattern, in the current frame.
+    let mut bindings = vec![];
+    if !self.match_pattern(&children[0], &value, &mut bindings) {
+      return Err(RuntimeError::PatternMismatch.into());
+    }
+    self.locals().extend(bindings);
+    Ok(Value::Unit)
+  }
+
+  // If the `Node` is a `BigNumber`, parse its digits into a bignum.
+  fn run_big_number(&mut self, value: &str) -> Result<Value, Box<Error>> {
+    match BigInt::parse(value) {
+      Some(value) => Ok(big_value(value)),
+      None => Err(RuntimeError::InvalidNode { node: "number" }.into()),
+    }
+  }
+
+  // If the `Node` is an `Interpolation`, evaluate each embedded expression and join the parts into one string,
+  // formatting values the same way `to_string` does.
+  fn run_interpolation(&mut self, children: &[Node]) -> Result<Value, Box<Error>> {
+    let mut text = String::new();
+    for n in children {
+      match self.run(n)? {
+        Value::String(value) => text.push_str(&value),
+        value => text.push_str(&value.to_string()),
+      }
+    }
+    Ok(Value::String(text))
+  }
+}
+
+// Run a program as a script: top-level statements execute in order, then `main` is called if the
+// program defines one. Without a `main`, the value of the last top-level statement is returned.
+impl Runtime {
+
+  // Check the parts of the program that can be verified before it runs. Struct literals naming a
+  // declared struct must list exactly its fields, struct patterns may only name declared fields,
+  // matches over an enum must be exhaustive, and calls to functions defined with `fn` must fit their
+  // parameters; anything else is checked when it is evaluated. Calls to a name in `variables` might
+  // reach a function value instead, so they are left to the runtime.
+  fn check(&self, node: &Node, variables: &HashSet<String>) -> Result<(), Box<Error>> {
+    if let Node::FunctionCall { name, children, span } = node {
+      if let (Some(body), false) = (self.functions.get(name), variables.contains(name)) {
+        let params = match &body[0] {
+          Node::FunctionArguments { children } => &children[..],
+          _ => &[],
+        };
+        let args = match children.first() {
+          Some(Node::FunctionArguments { children }) => &children[..],
+          _ => &[],
+        };
+        let names: Vec<Option<&str>> = args.iter().map(|arg| match arg {
+          Node::NamedArgument { name, .. } => Some(name.as_str()),
+          _ => None,
+        }).collect();
+        // How many arguments a spread list holds is only known when it runs.
+        if !args.iter().any(|arg| matches!(arg, Node::Spread { .. })) {
+          match_arguments(name, params, &names).map_err(|error| Error::from(error).at(*span))?;
+        }
+      }
+    }
+    // Also checked for every call, in case the call comes before the definition.
+    if let Node::FunctionArguments { children } = node {
+      check_parameters(children)?;
+    }
+    if let Node::StructLiteral { name, children } = node {
+      if let Some(declared) = self.structs.get(name) {
+        for init in children {
+          if let Node::FieldInit { name, .. } = init {
+            if !declared.contains(name) {
+              return Err(RuntimeError::UnknownField { name: name.clone() }.into());
             }
-            if children.len() > 2 { // Check if children has 3 elements or more, this indicates that there is an else if branch
-                if let Node::ElifBranch { children } = &children[1] { // match the elif branch in the 2nd index or 1
-                    match self.run(&children[0]) { // match condition in else if branch
-                        Ok(Value::Bool(true)) => { // if true, we return value inside
-                            return self.run(&children[1]);
-                        },
-                        Ok(Value::Bool(false)) => (), // else we move on to else branch
-                        _ => return Err("Invalid elif expression"),
-                    }
-                    if let Node::ElseBranch { children } = &children[2] { // if elif branch fails, we run else branch
-                        return self.run(&children[0]);
-                    }
-                }
-            }        
-            else if let Node::ElseBranch { children } = &children[1] { // children length is less than 3, then we only have if and else branch
-                return self.run(&children[0]); // run value in else branch if if_branch fails
+          }
+        }
+        let given = |field: &String| children.iter().any(|init| matches!(init, Node::FieldInit { name, .. } if name == field));
+        if let Some(missing) = declared.iter().find(|field| !given(field)) {
+          return Err(RuntimeError::MissingField { name: missing.clone() }.into());
+        }
+      }
+    }
+    if let Node::StructPattern { name, children } = node {
+      if let Some(declared) = self.structs.get(name) {
+        for field in children {
+          if let Node::FieldInit { name, .. } = field {
+            if !declared.contains(name) {
+              return Err(RuntimeError::UnknownField { name: name.clone() }.into());
             }
-            return Err("Invalid if expression");
+          }
+        }
+      }
+    }
+    if let Node::MatchExpression { children } = node {
+      self.check_exhaustive(&children[1..])?;
+    }
+    for child in node.children() {
+      // Point at the innermost statement containing the mistake.
+      self.check(child, variables).map_err(|error| match node {
+        Node::Statement { span, .. } => Box::new(error.at(*span)),
+        _ => error,
+      })?;
+    }
+    Ok(())
+  }
+
+  // When every arm of a `match` matches variants of one known enum, make sure all of its variants are covered.
+  // An arm only covers its variant if none of the variant's fields are matched against a literal.
+  fn check_exhaustive(&self, arms: &[Node]) -> Result<(), RuntimeError> {
+    let mut enum_name = None;
+    let mut covered = vec![];
+    for arm in arms {
+      match &arm.children()[0] {
+        Node::VariantPattern { name, children } => {
+          match self.variants.get(name) {
+            Some((owner, _)) => enum_name = Some(owner),
+            None => return Err(RuntimeError::UndefinedVariant { name: name.clone() }),
+          }
+          if children.iter().all(|p| matches!(p, Node::Wildcard | Node::Identifier { .. })) {
+            covered.push(name);
+          }
         },
-        // If the `Node` is a `FunctionCall`, evaluate it.
-        Node::FunctionCall { name, children } => {
-            // Extract the input arguments.
-            let in_args = if children.len() > 0 {
-                match &children[0] {
-                    Node::FunctionArguments { children } => {
-                        children
-                    },
-                    _ => children,
-                }
-            } else {
-                children
-            };
-            // Create a new frame for local variables.
-            let mut new_frame = HashMap::new();
-            // Initialize the result to an error message.
-            let mut result: Result<Value, &'static str> = Err("Undefined function");
-            // Save a raw pointer to the `Runtime` instance for use in the nested closure.
-            let rt = self as *mut Runtime;
-            // Find the named function and evaluate its body.
-            match self.functions.get(name) {
-                Some(statements) => {
-                    {
-                        // If the function has input arguments, bind their values to the corresponding parameters.
-                        match statements[0].clone() {
-                            Node::FunctionArguments { children } => {
-                                for (ix, arg) in children.iter().enumerate() {
-                                    // Use unsafe Rust code to call `run` on the input argument and handle any errors.
-                                    unsafe {
-                                        let result = (*rt).run(&in_args[ix])?;
-                                        match arg {
-                                            Node::Expression { children } => {
-                                                match &children[0] {
-                                                    Node::Identifier { value } => {
-                                                        new_frame.insert(value.clone(), result);
-                                                    },
-                                                    _ => (),
-                                                }
-                                            }
-                                            _ => (),
-                                        }
-                                    }
-                                }
-                            }
-                            _ => (),
-                        }
-                    }
-                    // Push the new frame onto the stack.
-                    self.stack.push(new_frame);
-                    // Evaluate each statement in the function body.
-                    for n in statements.clone() {
-                        result = self.run(&n);
-                    }
-                    // Pop the frame off the stack.
-                    self.stack.pop();
-                },
-                None => (),
-            };
-            // Return the result of evaluating the function.
-            result
+        // A wildcard or a binding matches anything.
+        Node::Wildcard | Node::Identifier { .. } => return Ok(()),
+        _ => (),
+      }
+    }
+    if let Some(variants) = enum_name.and_then(|name| self.enums.get(name)) {
+      if variants.iter().any(|variant| !covered.contains(&variant)) {
+        return Err(RuntimeError::NonExhaustiveMatch);
+      }
+    }
+    Ok(())
+  }
+
+  // Build a value of the variant `name`, which must be given exactly as many fields as it declares.
+  fn construct_variant(&self, name: &str, fields: Vec<Value>) -> Result<Value, RuntimeError> {
+    let (enum_name, arity) = match self.variants.get(name) {
+      Some(variant) => variant.clone(),
+      None => return Err(RuntimeError::UndefinedVariant { name: name.to_string() }),
+    };
+    if fields.len() != arity {
+      return Err(RuntimeError::ArityMismatch { name: name.to_string(), expected: arity, found: fields.len() });
+    }
+    Ok(Value::Variant(Rc::new(Variant { enum_name, name: name.to_string(), fields })))
+  }
+
+  // Check whether `value` matches `pattern`, collecting the names it binds.
+  fn match_pattern(&self, pattern: &Node, value: &Value, bindings: &mut Vec<(String, Value)>) -> bool {
+    match (pattern, value) {
+      (Node::Wildcard, _) => true,
+      (Node::Identifier { value: name }, _) => {
+        bindings.push((name.clone(), value.clone()));
+        true
+      },
+      (Node::Number { value: expected }, Value::Number(actual)) => expected == actual,
+      (Node::Bool { value: expected }, Value::Bool(actual)) => expected == actual,
+      (Node::String { value: expected }, Value::String(actual)) => expected == actual,
+      (Node::VariantPattern { name, children }, Value::Variant(variant)) => {
+        *name == variant.name
+          && children.len() == variant.fields.len()
+          && children.iter().zip(&variant.fields).all(|(p, v)| self.match_pattern(p, v, bindings))
+      },
+      // List and tuple patterns need exactly one element per sub-pattern.
+      (Node::ListPattern { children }, Value::List(values)) => {
+        let values = values.borrow();
+        children.len() == values.len()
+          && children.iter().zip(values.iter()).all(|(p, v)| self.match_pattern(p, v, bindings))
+      },
+      (Node::TuplePattern { children }, Value::Tuple(values)) => {
+        children.len() == values.len()
+          && children.iter().zip(values).all(|(p, v)| self.match_pattern(p, v, bindings))
+      },
+      // A struct pattern only looks at the fields it names.
+      (Node::StructPattern { name, children }, Value::Struct(target)) => {
+        let target = target.borrow();
+        *name == target.name
+          && children.iter().all(|field| match field {
+            Node::FieldInit { name, children } => match target.get(name) {
+              Some(v) => self.match_pattern(&children[0], v, bindings),
+              None => false,
+            },
+            _ => false,
+          })
+      },
+      _ => false,
+    }
+  }
+
+  // Call the function `name`.
+  fn call_function(&mut self, name: &str, children: &[Node], site: Option<Span>) -> Result<Value, Box<Error>> {
+    match self.resolve_call(name, children)? {
+      Callee::Function(closure, args) => self.call_closure(&closure, args, site),
+      Callee::Value(value) => Ok(value),
+    }
+  }
+
+  // Work out what a call to `name` runs: a variable holding a function value, a function defined with `fn`, an
+  // enum variant constructor or a builtin, in that order. The arguments are evaluated before the function is
+  // looked up, so nothing is borrowed from the runtime while they run.
+  fn resolve_call(&mut self, name: &str, children: &[Node]) -> Result<Callee, Box<Error>> {
+    // A name that means nothing is reported as such, whatever its arguments would have done.
+    let defined = self.lookup(name).is_some() || self.functions.contains_key(name) || self.variants.contains_key(name);
+    if !defined && !builtins::exists(name) {
+      return Err(RuntimeError::UndefinedFunction { name: name.to_string() }.into());
+    }
+    let args = self.eval_arguments(children)?;
+    // A variable holding a function value takes precedence over a named function.
+    match self.lookup(name) {
+      Some(Value::Function(closure)) => return Ok(Callee::Function(closure, args)),
+      Some(value) if !self.functions.contains_key(name) => return Err(RuntimeError::NotAFunction { found: value.type_name() }.into()),
+      _ => (),
+    }
+    // A function defined with `fn` runs like a function value that captured nothing.
+    if let Some(body) = self.functions.get(name) {
+      let closure = Closure { name: name.to_string(), body: Rc::clone(body), env: None };
+      return Ok(Callee::Function(Rc::new(closure), args));
+    }
+    // Calling a variant name constructs a value of that enum.
+    if self.variants.contains_key(name) {
+      return Ok(Callee::Value(self.construct_variant(name, positional(name, args)?)?));
+    }
+    // Fall back to a builtin when no user function has this name.
+    Ok(Callee::Value(builtins::call(name, positional(name, args)?)?))
+  }
+
+  // Evaluate the value of a `return` statement. A call to a script function there is a tail call: its arguments
+  // are evaluated and bound now, but its body only runs once the returning function's frame is gone, so
+  // recursion through `return` runs in constant space. `match` passes tail position on to the arm it picks.
+  // Since the call depth no longer grows, the depth limit can't stop such recursion either: `fn f(n) { return
+  // f(n); }` loops forever unless the runtime was given a step limit with `with_max_steps`.
+  fn run_tail(&mut self, mut node: &Node) -> Result<Value, Box<Error>> {
+    // Outside a function there is nothing to replace, and inside a `try` the call has to finish first.
+    if !matches!(self.calls.last(), Some(call) if call.trying == 0) {
+      return self.run(node);
+    }
+    // Most returned values are not calls, so this stays on the host stack while they run, and is kept small.
+    loop {
+      node = match node {
+        Node::Expression { children } => &children[0],
+        Node::MatchExpression { children } => self.match_arm(children)?,
+        Node::FunctionCall { name, children, span } => return self.tail_call_function(name, children, *span),
+        Node::Call { children, span } => return self.tail_call_value(children, *span),
+        _ => return self.run(node),
+      };
+    }
+  }
+
+  // Make a tail call to the function `name`. Builtins and variant constructors just return their value.
+  fn tail_call_function(&mut self, name: &str, children: &[Node], span: Span) -> Result<Value, Box<Error>> {
+    let result = match self.resolve_call(name, children) {
+      Ok(Callee::Function(closure, args)) => self.tail_call(&closure, args, span),
+      Ok(Callee::Value(value)) => Ok(value),
+      Err(error) => Err(error),
+    };
+    result.map_err(|error| self.locate(error, span))
+  }
+
+  // Make a tail call to a function value, such as `adder(1)(2)`.
+  fn tail_call_value(&mut self, children: &[Node], span: Span) -> Result<Value, Box<Error>> {
+    let result = self.run(&children[0]).and_then(|callee| {
+      let args = self.eval_arguments(&children[1..])?;
+      match callee {
+        Value::Function(closure) => self.tail_call(&closure, args, span),
+        _ => Err(RuntimeError::NotAFunction { found: callee.type_name() }.into()),
+      }
+    });
+    result.map_err(|error| self.locate(error, span))
+  }
+
+  // Bind the arguments of a tail call made at `site`, and leave it for `call_closure` to run in place of the
+  // current function.
+  fn tail_call(&mut self, closure: &Closure, args: Vec<Argument>, site: Span) -> Result<Value, Box<Error>> {
+    let frame = self.bind_arguments(&closure.name, parameters(&closure.body).0, args, closure.env.clone())?;
+    self.tail_call = Some(TailCall { name: closure.name.clone(), body: Rc::clone(&closure.body), frame, site });
+    Ok(Value::Unit)
+  }
+
+  // Evaluate the value a `match` expression looks at, bind the names in the first arm whose pattern matches it,
+  // and return that arm's body.
+  fn match_arm<'a>(&mut self, children: &'a [Node]) -> Result<&'a Node, Box<Error>> {
+    let value = self.run(&children[0])?;
+    for arm in &children[1..] {
+      if let Node::MatchArm { children } = arm {
+        let mut bindings = vec![];
+        if self.match_pattern(&children[0], &value, &mut bindings) {
+          // Bound names live in the current frame, like variables made with `let`.
+          self.locals().extend(bindings);
+          return Ok(&children[1]);
+        }
+      }
+    }
+    Err(RuntimeError::NoMatchingArm.into())
+  }
+
+  // Look up a variable in the current scope, the scopes it was created in, then the globals.
+  fn lookup(&self, name: &str) -> Option<Value> {
+    self.scope_of(name).and_then(|scope| scope.variables.borrow().get(name).cloned())
+  }
+
+  // Find the innermost scope that defines `name`.
+  fn scope_of(&self, name: &str) -> Option<&Scope> {
+    let mut scope = self.stack.last().map(|scope| &**scope);
+    while let Some(current) = scope {
+      if current.variables.borrow().contains_key(name) {
+        return Some(current);
+      }
+      scope = current.parent.as_deref();
+    }
+    Some(&*self.stack[0]).filter(|globals| globals.variables.borrow().contains_key(name))
+  }
+
+  // The variables of the current scope, where `let` and other bindings define new names.
+  fn locals(&self) -> std::cell::RefMut<'_, HashMap<String, Value>> {
+    self.stack[self.stack.len() - 1].variables.borrow_mut()
+  }
+
+  // Evaluate call arguments left to right, in the caller's frame, in the order they are written rather than the
+  // order of the parameters. `children` is either empty or a single `FunctionArguments` node.
+  fn eval_arguments(&mut self, children: &[Node]) -> Result<Vec<Argument>, Box<Error>> {
+    let mut args = vec![];
+    for child in children {
+      match child {
+        Node::FunctionArguments { children } => {
+          for arg in children {
+            self.eval_argument(arg, &mut args)?;
+          }
         },
-        // If the `Node` is a `FunctionDefine`, add it to the list of functions.
-        Node::FunctionDefine { children } => {
-            let (head, tail) = children.split_at(1);
-            match &head[0] {
-                Node::Identifier { value } => {
-                    self.functions.insert(value.to_string(), tail.to_vec());
-                },
-                _ => (),
-            }
-            Ok(Value::Bool(true))
+        _ => self.eval_argument(child, &mut args)?,
+      }
+    }
+    Ok(args)
+  }
+
+  fn eval_argument(&mut self, arg: &Node, args: &mut Vec<Argument>) -> Result<(), Box<Error>> {
+    match arg {
+      Node::NamedArgument { name, children } => args.push(Argument { name: Some(name.clone()), value: self.run(&children[0])? }),
+      // Each element of a spread list becomes a positional argument.
+      Node::Spread { children } => {
+        match self.run(&children[0])? {
+          Value::List(values) => args.extend(values.borrow().iter().map(|value| Argument { name: None, value: value.clone() })),
+          value => return Err(RuntimeError::NotIterable { found: value.type_name() }.into()),
+        }
+      },
+      _ => args.push(Argument { name: None, value: self.run(arg)? }),
+    }
+    Ok(())
+  }
+
+  // Bind the arguments of a call to `function` to its parameters, in a new scope inside `env`. Parameters left
+  // without an argument get their default value, evaluated in the new scope so it can refer to the parameters
+  // before it.
+  fn bind_arguments(&mut self, function: &str, params: &[Node], args: Vec<Argument>, env: Option<Rc<Scope>>) -> Result<Rc<Scope>, Box<Error>> {
+    let (slots, rest) = match_arguments(function, params, &argument_names(&args))?;
+    let mut values: Vec<Option<Value>> = args.into_iter().map(|arg| Some(arg.value)).collect();
+    self.stack.push(Rc::new(Scope { variables: RefCell::default(), parent: env }));
+    for (param, slot) in params.iter().zip(slots) {
+      if let Node::RestParameter { name } = param {
+        let list = rest.iter().filter_map(|ix| values[*ix].take()).collect();
+        self.locals().insert(name.clone(), Value::list(list));
+      }
+      if let Node::Parameter { name, children } = param {
+        let value = match slot {
+          Some(ix) => values[ix].take().unwrap_or(Value::Unit),
+          None => match self.run(&children[0]) {
+            Ok(value) => value,
+            Err(error) => {
+              self.stack.pop();
+              return Err(error);
+            },
+          },
+        };
+        self.locals().insert(name.clone(), value);
+      }
+    }
+    Ok(self.stack.pop().unwrap_or_default())
+  }
+
+  // Call a function value. Every call to a script function ends up here, and follows the same convention: the
+  // caller has already evaluated the arguments left to right in its own scope, then they are bound to the
+  // parameters in a fresh frame whose parent is the scope the function was created in, and the body runs in that
+  // frame. The body sees its own frame, the scopes it was created in and the globals, never the caller's locals,
+  // and the frame is gone once the call returns unless a closure made inside it still holds it.
+  // A tail call made by the body runs next, in the same loop, standing in for the function that made it.
+  fn call_closure(&mut self, closure: &Closure, args: Vec<Argument>, site: Option<Span>) -> Result<Value, Box<Error>> {
+    // Fail before the host stack runs out, which would abort the whole process.
+    let here = stack_address();
+    if self.calls.is_empty() {
+      self.stack_base = here;
+    }
+    if self.calls.len() >= self.max_call_depth {
+      return Err(RuntimeError::StackOverflow { function: closure.name.clone(), depth: self.calls.len() }.into());
+    }
+    if self.stack_base.abs_diff(here) > self.max_stack_size {
+      return Err(RuntimeError::StackExhausted { function: closure.name.clone(), limit: self.max_stack_size }.into());
+    }
+    let mut frame = self.bind_arguments(&closure.name, parameters(&closure.body).0, args, closure.env.clone())?;
+    let mut body = Rc::clone(&closure.body);
+    self.calls.push(Call { name: closure.name.clone(), site, trying: 0, tail_caller: None });
+    let result = loop {
+      self.stack.push(frame);
+      let mut result = Ok(Value::Unit);
+      for n in parameters(&body).1 {
+        result = self.run(n);
+        if result.is_err() || self.returning {
+          break;
+        }
+      }
+      self.returning = false;
+      self.stack.pop();
+      let Some(tail_call) = self.tail_call.take() else {
+        break result;
+      };
+      // Backtraces keep only the latest function that made a tail call, and count the ones it replaced.
+      let last = self.calls.len() - 1;
+      let call = &mut self.calls[last];
+      let elided = call.tail_caller.as_ref().map_or(0, |caller| caller.elided + 1);
+      let caller = std::mem::replace(&mut call.name, tail_call.name);
+      call.tail_caller = Some(Frame { function: caller, span: tail_call.site, elided });
+      frame = tail_call.frame;
+      body = tail_call.body;
+    };
+    self.calls.pop();
+    result
+  }
+
+  // Give an error the location `span`, unless it already has a more precise one, and record the backtrace of
+  // the calls running at that point. Each function is reported at the call it was making when the error
+  // happened; the innermost one at `span` itself.
+  fn locate(&self, mut error: Box<Error>, span: Span) -> Box<Error> {
+    if error.span.is_some() {
+      return error;
+    }
+    error.span = Some(span);
+    let mut location = span;
+    for call in self.calls.iter().rev() {
+      error.trace.push(Frame { function: call.name.clone(), span: location, elided: 0 });
+      // A function started by a tail call is followed by the function that made it, as it was when it did.
+      if let Some(caller) = &call.tail_caller {
+        error.trace.push(caller.clone());
+      }
+      match call.site {
+        Some(site) => location = site,
+        None => return error,
+      }
+    }
+    // The outermost code is the script's top-level statements.
+    error.trace.push(Frame { function: "<script>".to_string(), span: location, elided: 0 });
+    error
+  }
+}
+
+// Collect every name the program binds as a variable: parameters, and the names in `let`, `for`, `catch` and
+// `match` patterns.
+fn bound_names(node: &Node, names: &mut HashSet<String>) {
+  match node {
+    Node::Parameter { name, .. } | Node::RestParameter { name } => {
+      names.insert(name.clone());
+    },
+    Node::VariableDefine { children } | Node::ForLoop { children } | Node::MatchArm { children } => {
+      pattern_names(&children[0], names);
+    },
+    Node::TryCatch { children } => pattern_names(&children[1], names),
+    _ => (),
+  }
+  for child in node.children() {
+    bound_names(child, names);
+  }
+}
+
+fn pattern_names(pattern: &Node, names: &mut HashSet<String>) {
+  if let Node::Identifier { value } = pattern {
+    names.insert(value.clone());
+  }
+  for child in pattern.children() {
+    pattern_names(child, names);
+  }
+}
+
+// An evaluated call argument, and the parameter it was passed to by name, if any.
+struct Argument {
+  name: Option<String>,
+  value: Value,
+}
+
+fn argument_names(args: &[Argument]) -> Vec<Option<&str>> {
+  args.iter().map(|arg| arg.name.as_deref()).collect()
+}
+
+// Builtins and variant constructors only take arguments by position.
+fn positional(function: &str, args: Vec<Argument>) -> Result<Vec<Value>, RuntimeError> {
+  args.into_iter().map(|arg| match arg.name {
+    Some(name) => Err(RuntimeError::UnknownArgument { function: function.to_string(), name }),
+    None => Ok(arg.value),
+  }).collect()
+}
+
+// Make sure parameters without a default come before those with one, so positional arguments always reach them.
+fn check_parameters(params: &[Node]) -> Result<(), RuntimeError> {
+  let mut defaulted = false;
+  for param in params {
+    if let Node::Parameter { name, children } = param {
+      if defaulted && children.is_empty() {
+        return Err(RuntimeError::RequiredAfterDefault { name: name.clone() });
+      }
+      defaulted |= !children.is_empty();
+    }
+  }
+  Ok(())
+}
+
+// Work out which argument of a call each parameter of `function` gets. Positional arguments fill the parameters
+// in order, and named arguments the parameter with their name. A parameter without an argument is `None`, and
+// must have a default value. Positional arguments beyond the parameters go to the rest parameter, if there is
+// one, and are returned separately. `args` holds the name of each argument passed by name.
+fn match_arguments(function: &str, params: &[Node], args: &[Option<&str>]) -> Result<(Vec<Option<usize>>, Vec<usize>), RuntimeError> {
+  check_parameters(params)?;
+  let mut slots = vec![None; params.len()];
+  let positional: Vec<usize> = (0..args.len()).filter(|ix| args[*ix].is_none()).collect();
+  let fixed = params.iter().filter(|param| matches!(param, Node::Parameter { .. })).count();
+  let variadic = fixed < params.len();
+  if positional.len() > fixed && !variadic {
+    return Err(RuntimeError::ArityMismatch { name: function.to_string(), expected: fixed, found: positional.len() });
+  }
+  for (slot, ix) in slots.iter_mut().zip(&positional[..positional.len().min(fixed)]) {
+    *slot = Some(*ix);
+  }
+  let rest = positional.iter().skip(fixed).copied().collect();
+  for (ix, arg) in args.iter().enumerate() {
+    if let Some(arg) = arg {
+      let param = params.iter().position(|param| matches!(param, Node::Parameter { name, .. } if name == arg));
+      match param {
+        None => return Err(RuntimeError::UnknownArgument { function: function.to_string(), name: arg.to_string() }),
+        Some(param) if slots[param].is_some() => {
+          return Err(RuntimeError::DuplicateArgument { function: function.to_string(), name: arg.to_string() });
         },
-        // If the `Node` is a `FunctionReturn`, evaluate its child node.
-        Node::FunctionReturn { children } => {
-            self.run(&children[0])
+        Some(param) => slots[param] = Some(ix),
+      }
+    }
+  }
+  for (param, slot) in params.iter().zip(&slots) {
+    if let (Node::Parameter { name, children }, None) = (param, slot) {
+      if children.is_empty() {
+        // Without named arguments, a missing argument just means the call has too few.
+        if positional.len() == args.len() {
+          let required = params.iter().filter(|param| matches!(param, Node::Parameter { children, .. } if children.is_empty())).count();
+          return Err(RuntimeError::ArityMismatch { name: function.to_string(), expected: required, found: args.len() });
+        }
+        return Err(RuntimeError::MissingArgument { function: function.to_string(), name: name.clone() });
+      }
+    }
+  }
+  Ok((slots, rest))
+}
+
+// Evaluate a math operator on two floats. Integers mixed with floats are promoted before getting here.
+fn float_math(name: &str, lhs: f64, rhs: f64) -> Result<Value, RuntimeError> {
+  match name {
+    "+" => Ok(Value::Float(lhs + rhs)),
+    "-" => Ok(Value::Float(lhs - rhs)),
+    "*" => Ok(Value::Float(lhs * rhs)),
+    "/" => {
+      if rhs == 0.0 {
+        return Err(RuntimeError::DivisionByZero);
+      }
+      Ok(Value::Float(lhs / rhs))
+    },
+    "^" => Ok(Value::Float(lhs.powf(rhs))),
+    "//" => {
+      if rhs == 0.0 {
+        return Err(RuntimeError::DivisionByZero);
+      }
+      Ok(Value::Float((lhs / rhs).floor()))
+    },
+    "%" => {
+      if rhs == 0.0 {
+        return Err(RuntimeError::DivisionByZero);
+      }
+      let remainder = lhs % rhs;
+      if remainder != 0.0 && (remainder < 0.0) != (rhs < 0.0) {
+        Ok(Value::Float(remainder + rhs))
+      } else {
+        Ok(Value::Float(remainder))
+      }
+    },
+    "&" | "|" | "<<" | ">>" => Err(RuntimeError::InvalidOperand { op: name.to_string(), found: "float" }),
+    _ => Err(RuntimeError::UnknownOperator { op: name.to_string() }),
+  }
+}
+
+// The largest result, in bits, that `^` and `<<` will compute.
+const MAX_POWER_BITS: i64 = 1 << 20;
+
+// Apply an arithmetic operator to two values.
+fn math(name: &str, lhs: Value, rhs: Value) -> Result<Value, RuntimeError> {
+  let types = (lhs.type_name(), rhs.type_name());
+  match (lhs, rhs) {
+    // If both children are `Number` values, extract their values and evaluate the expression.
+    // Results that overflow an `i64` are computed again as bignums.
+    (Value::Number(lhs), Value::Number(rhs)) => {
+      match name {
+        // If the operator is `+`, add the values.
+        "+" => match lhs.checked_add(rhs) {
+          Some(result) => Ok(Value::Number(result)),
+          None => big_math(name, BigInt::from_i64(lhs), BigInt::from_i64(rhs)),
         },
-        // If the `Node` is an `Identifier`, look up its value in the current frame.
-        Node::Identifier { value } => {
-            let last = self.stack.len() - 1;
-            match self.stack[last].get(value) {
-                Some(id_value) => Ok(id_value.clone()),
-                None => Err("Undefined variable"),
-            }
+        // If the operator is `-`, subtract the values.
+        "-" => match lhs.checked_sub(rhs) {
+          Some(result) => Ok(Value::Number(result)),
+          None => big_math(name, BigInt::from_i64(lhs), BigInt::from_i64(rhs)),
+        },
+        // If the operator is `*`, multiply the values.
+        "*" => match lhs.checked_mul(rhs) {
+          Some(result) => Ok(Value::Number(result)),
+          None => big_math(name, BigInt::from_i64(lhs), BigInt::from_i64(rhs)),
         },
-        // If the `Node` is a `Statement`, evaluate its child node.
-        Node::Statement { children } => {
-            match children[0] {
-                Node::VariableDefine { .. } |
-                Node::FunctionReturn { .. } => {
-                    self.run(&children[0])
-                },
-                _ => Err("Unknown Statement"),
+        // If the operator is `/`, divide the values. Only `i64::MIN / -1` overflows.
+        "/" => match (lhs, rhs) {
+          (_, 0) => Err(RuntimeError::DivisionByZero),
+          (i64::MIN, -1) => big_math(name, BigInt::from_i64(lhs), BigInt::from_i64(rhs)),
+          _ => Ok(Value::Number(lhs / rhs)),
+        },
+        // If the operator is `^`, raise the left value to the power of the right value.
+        // Negative, very large or overflowing powers are left to the bignum code.
+        "^" => match u32::try_from(rhs).ok().and_then(|exponent| lhs.checked_pow(exponent)) {
+          Some(result) => Ok(Value::Number(result)),
+          None => big_math(name, BigInt::from_i64(lhs), BigInt::from_i64(rhs)),
+        },
+        // If the operator is `//`, divide rounding toward negative infinity, so `-7 // 2` is `-4`.
+        "//" => match (lhs, rhs) {
+          (_, 0) => Err(RuntimeError::DivisionByZero),
+          (i64::MIN, -1) => big_math(name, BigInt::from_i64(lhs), BigInt::from_i64(rhs)),
+          _ => {
+            let quotient = lhs / rhs;
+            if lhs % rhs != 0 && (lhs < 0) != (rhs < 0) {
+              Ok(Value::Number(quotient - 1))
+            } else {
+              Ok(Value::Number(quotient))
             }
+          },
         },
-        // If the `Node` is a `VariableDefine`, evaluate its expression and bind the result to a new variable.
-        Node::VariableDefine { children } => {
-            // Extract the variable name.
-            let name: String = match &children[0] {
-                Node::Identifier { value } => value.clone(),
-                _ => "".to_string(),
-            };
-            // Evaluate the expression.
-            let value = self.run(&children[1])?;
-            // Add the variable to the current frame.
-            let last = self.stack.len() - 1;
-            self.stack[last].insert(name, value.clone());
-            // Return the value.
-            Ok(value)
-        }
-        // If the `Node` is an `Expression`, evaluate its child node.
-        Node::Expression { children } => {
-            match children[0] {
-                Node::MathExpression { .. } |
-                Node::IfExpression { .. } |
-                Node::ComparisonExpression { .. } |
-                Node::Number { .. } |
-                Node::FunctionCall { .. } |
-                Node::String { .. } |
-                Node::Bool { .. } |
-                Node::Identifier { .. } => {
-                    self.run(&children[0])
-                },
-                _ => Err("Unknown Expression"),
+        // If the operator is `%`, take the remainder of `//`, which has the sign of the right value.
+        "%" => match (lhs, rhs) {
+          (_, 0) => Err(RuntimeError::DivisionByZero),
+          (_, -1) => Ok(Value::Number(0)),
+          _ => {
+            let remainder = lhs % rhs;
+            if remainder != 0 && (remainder < 0) != (rhs < 0) {
+              Ok(Value::Number(remainder + rhs))
+            } else {
+              Ok(Value::Number(remainder))
             }
-        }
-        // If the `Node` is a `Number`, wrap its value in a `Value::Number` and return it.
-        Node::Number { value } => {
-            Ok(Value::Number(*value))
-        }
-        // If the `Node` is a `String`, wrap its value in a `Value::String` and return it.
-        Node::String { value } => {
-            Ok(Value::String(value.clone()))
-        }
-        // If the `Node` is a `Bool`, wrap its value in a `Value::Bool` and return it.
-        Node::Bool { value } => {
-            Ok(Value::Bool(*value))
-        }
-        // If the `Node` is of an unhandled type, return an error message.
-        _ => {
-            Err("Unhandled Node")
+          },
+        },
+        // If the operator is `&` or `|`, combine the two's complement bits of the values.
+        "&" => Ok(Value::Number(lhs & rhs)),
+        "|" => Ok(Value::Number(lhs | rhs)),
+        // If the operator is `<<`, shift left. Bits shifted out of an `i64` carry on as a bignum.
+        "<<" => match rhs {
+          _ if rhs < 0 => Err(RuntimeError::NegativeShift),
+          0..=63 if (lhs << rhs) >> rhs == lhs => Ok(Value::Number(lhs << rhs)),
+          _ => big_math(name, BigInt::from_i64(lhs), BigInt::from_i64(rhs)),
+        },
+        // If the operator is `>>`, shift right keeping the sign, which rounds toward negative infinity.
+        ">>" => match rhs {
+          _ if rhs < 0 => Err(RuntimeError::NegativeShift),
+          _ => Ok(Value::Number(lhs >> rhs.min(63))),
         },
+        // If the operator is not recognized, return an error message.
+        _ => Err(RuntimeError::UnknownOperator { op: name.to_string() }),
+      }
     }
+    // If either child is a bignum, evaluate the expression on bignums.
+    (Value::BigInt(lhs), Value::Number(rhs)) => big_math(name, lhs, BigInt::from_i64(rhs)),
+    (Value::Number(lhs), Value::BigInt(rhs)) => big_math(name, BigInt::from_i64(lhs), rhs),
+    (Value::BigInt(lhs), Value::BigInt(rhs)) => big_math(name, lhs, rhs),
+    (Value::BigInt(lhs), Value::Float(rhs)) => float_math(name, lhs.to_f64(), rhs),
+    (Value::Float(lhs), Value::BigInt(rhs)) => float_math(name, lhs, rhs.to_f64()),
+    // If either child is a `Float`, promote the other to a `Float` and evaluate the expression.
+    (Value::Float(lhs), Value::Float(rhs)) => float_math(name, lhs, rhs),
+    (Value::Number(lhs), Value::Float(rhs)) => float_math(name, lhs as f64, rhs),
+    (Value::Float(lhs), Value::Number(rhs)) => float_math(name, lhs, rhs as f64),
+    // If both children are strings, `+` joins them.
+    (Value::String(lhs), Value::String(rhs)) if name == "+" => Ok(Value::String(lhs + &rhs)),
+    // If the operator doesn't apply to these types, return an error.
+    _ => Err(RuntimeError::TypeMismatch { op: name.to_string(), lhs: types.0, rhs: types.1 }),
+  }
+}
+
+// Apply a comparison operator to two values.
+fn comparison(name: &str, lhs: Value, rhs: Value) -> Result<Value, RuntimeError> {
+  let types = (lhs.type_name(), rhs.type_name());
+  // Evaluate the left and right children of the `Conditional expression`.
+  match (lhs, rhs) {
+    // If both children are `Number` values, extract their values and evaluate the expression.
+    (Value::Number(lhs), Value::Number(rhs)) => {
+      match name {
+        // If the operator is `>`, check if left side is greater than the right side, then return true or false
+        ">" => { if lhs > rhs {Ok(Value::Bool(true))} else {Ok(Value::Bool(false))} },
+        "<" => { if lhs < rhs {Ok(Value::Bool(true))} else {Ok(Value::Bool(false))}},
+        ">=" => { if lhs >= rhs {Ok(Value::Bool(true))} else {Ok(Value::Bool(false))}},
+        "<=" => { if lhs <= rhs {Ok(Value::Bool(true))} else {Ok(Value::Bool(false))}},
+        "==" => { if lhs == rhs {Ok(Value::Bool(true))} else {Ok(Value::Bool(false))}},
+        "!=" => { if lhs != rhs {Ok(Value::Bool(true))} else {Ok(Value::Bool(false))}},
+        // If the operator is not recognized, return an error message.
+        _ => Err(RuntimeError::UnknownOperator { op: name.to_string() }),
+      }
+    }
+    // If either child is a bignum, compare both as bignums, or as floats when the other is a `Float`.
+    (Value::BigInt(lhs), Value::Number(rhs)) => ordering_comparison(name, lhs.cmp(&BigInt::from_i64(rhs))),
+    (Value::Number(lhs), Value::BigInt(rhs)) => ordering_comparison(name, BigInt::from_i64(lhs).cmp(&rhs)),
+    (Value::BigInt(lhs), Value::BigInt(rhs)) => ordering_comparison(name, lhs.cmp(&rhs)),
+    (Value::BigInt(lhs), Value::Float(rhs)) => float_comparison(name, lhs.to_f64(), rhs),
+    (Value::Float(lhs), Value::BigInt(rhs)) => float_comparison(name, lhs, rhs.to_f64()),
+    // If either child is a `Float`, compare both as floats.
+    (Value::Float(lhs), Value::Float(rhs)) => float_comparison(name, lhs, rhs),
+    (Value::Number(lhs), Value::Float(rhs)) => float_comparison(name, lhs as f64, rhs),
+    (Value::Float(lhs), Value::Number(rhs)) => float_comparison(name, lhs, rhs as f64),
+    // if lhs and rhs are both boolean, match their equality or inequality
+    (Value::Bool(lhs), Value::Bool(rhs)) => {
+      match name {
+        "==" => { if lhs == rhs { Ok(Value::Bool(true)) } else { Ok(Value::Bool(false)) } },
+        "!=" => { if lhs != rhs { Ok(Value::Bool(true)) } else { Ok(Value::Bool(false)) } },
+        _ => Err(RuntimeError::TypeMismatch { op: name.to_string(), lhs: types.0, rhs: types.1 }),
+      }
+    }
+    // Strings compare lexicographically by character.
+    (Value::String(lhs), Value::String(rhs)) => ordering_comparison(name, lhs.cmp(&rhs)),
+    // lists, maps, structs, enum values and tuples compare their contents for equality or inequality
+    (lhs @ Value::Unit, rhs @ Value::Unit) |
+    (lhs @ Value::List(_), rhs @ Value::List(_)) |
+    (lhs @ Value::Map(_), rhs @ Value::Map(_)) |
+    (lhs @ Value::Struct(_), rhs @ Value::Struct(_)) |
+    (lhs @ Value::Variant(_), rhs @ Value::Variant(_)) |
+    (lhs @ Value::Tuple(_), rhs @ Value::Tuple(_)) => {
+      match name {
+        "==" => Ok(Value::Bool(lhs == rhs)),
+        "!=" => Ok(Value::Bool(lhs != rhs)),
+        _ => Err(RuntimeError::TypeMismatch { op: name.to_string(), lhs: types.0, rhs: types.1 }),
+      }
+    }
+    _ => Err(RuntimeError::TypeMismatch { op: name.to_string(), lhs: types.0, rhs: types.1 }),
+  }
+}
+
+// Evaluate a math operator on two bignums, e.g. when an `i64` result overflowed.
+fn big_math(name: &str, lhs: BigInt, rhs: BigInt) -> Result<Value, RuntimeError> {
+  match name {
+    "+" => Ok(big_value(lhs.add(&rhs))),
+    "-" => Ok(big_value(lhs.sub(&rhs))),
+    "*" => Ok(big_value(lhs.mul(&rhs))),
+    "/" => {
+      if rhs.is_zero() {
+        return Err(RuntimeError::DivisionByZero);
+      }
+      Ok(big_value(lhs.div_rem(&rhs).0))
+    },
+    "^" => {
+      if rhs < BigInt::zero() {
+        return Err(RuntimeError::NegativeExponent);
+      }
+      // 0, 1 and -1 stay small whatever the exponent is.
+      match lhs.to_i64() {
+        Some(0) | Some(1) if !rhs.is_zero() => return Ok(big_value(lhs)),
+        Some(-1) => {
+          let odd = !rhs.div_rem(&BigInt::from_i64(2)).1.is_zero();
+          return Ok(Value::Number(if odd { -1 } else { 1 }));
+        },
+        _ => (),
+      }
+      // Refuse results too large to compute in reasonable time and memory.
+      let exponent = rhs.to_i64().unwrap_or(i64::MAX);
+      if (lhs.bit_length() as i64).saturating_mul(exponent) > MAX_POWER_BITS {
+        return Err(RuntimeError::IntegerOverflow);
+      }
+      Ok(big_value(lhs.pow(exponent as u32)))
+    },
+    "//" | "%" => {
+      if rhs.is_zero() {
+        return Err(RuntimeError::DivisionByZero);
+      }
+      // `div_rem` truncates; step the quotient down when the remainder's sign disagrees with the divisor's.
+      let (mut quotient, mut remainder) = lhs.div_rem(&rhs);
+      if !remainder.is_zero() && (remainder < BigInt::zero()) != (rhs < BigInt::zero()) {
+        quotient = quotient.sub(&BigInt::from_i64(1));
+        remainder = remainder.add(&rhs);
+      }
+      Ok(big_value(if name == "//" { quotient } else { remainder }))
+    },
+    "<<" | ">>" => {
+      if rhs < BigInt::zero() {
+        return Err(RuntimeError::NegativeShift);
+      }
+      let shift = rhs.to_i64().unwrap_or(i64::MAX);
+      if name == ">>" {
+        // Shifting right by at least the bit length leaves only the sign.
+        if shift >= lhs.bit_length() as i64 {
+          return Ok(Value::Number(if lhs < BigInt::zero() { -1 } else { 0 }));
+        }
+        return big_math("//", lhs, BigInt::from_i64(2).pow(shift as u32));
+      }
+      if lhs.is_zero() {
+        return Ok(Value::Number(0));
+      }
+      if (lhs.bit_length() as i64).saturating_add(shift) > MAX_POWER_BITS {
+        return Err(RuntimeError::IntegerOverflow);
+      }
+      Ok(big_value(lhs.mul(&BigInt::from_i64(2).pow(shift as u32))))
+    },
+    "&" | "|" => Err(RuntimeError::IntegerTooLarge { op: name.to_string() }),
+    _ => Err(RuntimeError::UnknownOperator { op: name.to_string() }),
+  }
+}
+
+// Store an integer result as a `Number` when it fits in an `i64`, and as a bignum otherwise.
+fn big_value(value: BigInt) -> Value {
+  match value.to_i64() {
+    Some(value) => Value::Number(value),
+    None => Value::BigInt(value),
+  }
+}
+
+// Turn the ordering of two values into the result of a comparison operator.
+fn ordering_comparison(name: &str, ordering: std::cmp::Ordering) -> Result<Value, RuntimeError> {
+  match name {
+    ">" => Ok(Value::Bool(ordering.is_gt())),
+    "<" => Ok(Value::Bool(ordering.is_lt())),
+    ">=" => Ok(Value::Bool(ordering.is_ge())),
+    "<=" => Ok(Value::Bool(ordering.is_le())),
+    "==" => Ok(Value::Bool(ordering.is_eq())),
+    "!=" => Ok(Value::Bool(ordering.is_ne())),
+    _ => Err(RuntimeError::UnknownOperator { op: name.to_string() }),
+  }
+}
+
+// Evaluate a comparison operator on two floats.
+fn float_comparison(name: &str, lhs: f64, rhs: f64) -> Result<Value, RuntimeError> {
+  match name {
+    ">" => Ok(Value::Bool(lhs > rhs)),
+    "<" => Ok(Value::Bool(lhs < rhs)),
+    ">=" => Ok(Value::Bool(lhs >= rhs)),
+    "<=" => Ok(Value::Bool(lhs <= rhs)),
+    "==" => Ok(Value::Bool(lhs == rhs)),
+    "!=" => Ok(Value::Bool(lhs != rhs)),
+    _ => Err(RuntimeError::UnknownOperator { op: name.to_string() }),
+  }
+}
+
+// Split a function body into its parameters and its statements.
+fn parameters(body: &[Node]) -> (&[Node], &[Node]) {
+  match body.split_first() {
+    Some((Node::FunctionArguments { children }, statements)) => (children, statements),
+    _ => (&[], body),
+  }
+}
+
+// The address of a local variable, which shows how much of the host stack is in use.
+fn stack_address() -> usize {
+  let marker = 0u8;
+  std::ptr::addr_of!(marker) as usize
+}
+
+// Convert the value of an index expression into a position in a list of `len` elements.
+fn index_position(index: Value, len: usize) -> Result<usize, RuntimeError> {
+  match index {
+    Value::Number(ix) => list_index(ix, len),
+    Value::BigInt(ix) => Err(RuntimeError::IndexOutOfRange { index: ix.to_string(), len }),
+    index => Err(RuntimeError::InvalidIndex { found: index.type_name() }),
+  }
+}
+
+// Convert a script index into a position in a collection of `len` elements, rejecting anything out of range.
+fn list_index(ix: i64, len: usize) -> Result<usize, RuntimeError> {
+  if ix < 0 || ix as usize >= len {
+    return Err(RuntimeError::IndexOutOfBounds { index: ix, len });
   }
+  Ok(ix as usize)
 }
 
-pub fn start_interpreter(node: &Node) -> Result<Value, &'static str> {
-  let mut runtime = Runtime::new();
-  runtime.run(node);
-  let start_main = Node::FunctionCall{name: "main".to_string(), children: vec![]};
-  runtime.run(&start_main)
+pub fn start_interpreter(node: &Node) -> Result<Value, Error> {
+  Runtime::new().start(node)
 }
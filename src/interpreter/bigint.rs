@@ -0,0 +1,301 @@
+// Arbitrary-precision integers. Scripts switch to these automatically when an integer result no longer fits in
+// an `i64`, and switch back once it fits again, so a `BigInt` value is always outside the `i64` range.
+use std::cmp::Ordering;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+  negative: bool,
+  // Base 2^32 digits, least significant first, with no trailing zeros. Zero has no digits and is never negative.
+  magnitude: Vec<u32>,
+}
+
+impl BigInt {
+  pub fn zero() -> BigInt {
+    BigInt { negative: false, magnitude: vec![] }
+  }
+
+  pub fn from_i64(value: i64) -> BigInt {
+    let mut magnitude = vec![];
+    let mut rest = value.unsigned_abs();
+    while rest > 0 {
+      magnitude.push(rest as u32);
+      rest >>= 32;
+    }
+    BigInt { negative: value < 0, magnitude }
+  }
+
+  // Convert the integer part of a float. Infinities and NaN have no integer value.
+  pub fn from_f64(value: f64) -> Option<BigInt> {
+    if !value.is_finite() {
+      return None;
+    }
+    let value = value.trunc();
+    if value.abs() < (1u64 << 53) as f64 {
+      return Some(BigInt::from_i64(value as i64));
+    }
+    // Large floats are a 53-bit mantissa times a power of two.
+    let bits = value.abs().to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as u32 - 1075;
+    let mantissa = (bits & ((1u64 << 52) - 1)) | (1u64 << 52);
+    let result = BigInt::from_i64(mantissa as i64).mul(&BigInt::from_i64(2).pow(exponent));
+    Some(if value < 0.0 { result.neg() } else { result })
+  }
+
+  // Parse a decimal integer with an optional leading `-`.
+  pub fn parse(text: &str) -> Option<BigInt> {
+    let (negative, digits) = match text.strip_prefix('-') {
+      Some(digits) => (true, digits),
+      None => (false, text),
+    };
+    if digits.is_empty() {
+      return None;
+    }
+    let mut magnitude = vec![];
+    for digit in digits.chars() {
+      let digit = digit.to_digit(10)?;
+      mul_small_add(&mut magnitude, 10, digit);
+    }
+    Some(BigInt { negative, magnitude }.normalized())
+  }
+
+  pub fn to_i64(&self) -> Option<i64> {
+    if self.magnitude.len() > 2 {
+      return None;
+    }
+    let mut value: u64 = 0;
+    for digit in self.magnitude.iter().rev() {
+      value = (value << 32) | *digit as u64;
+    }
+    if self.negative {
+      if value <= i64::MAX as u64 + 1 {
+        return Some((value as i64).wrapping_neg());
+      }
+      None
+    } else {
+      i64::try_from(value).ok()
+    }
+  }
+
+  pub fn to_f64(&self) -> f64 {
+    let mut value = 0.0;
+    for digit in self.magnitude.iter().rev() {
+      value = value * 4294967296.0 + *digit as f64;
+    }
+    if self.negative { -value } else { value }
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.magnitude.is_empty()
+  }
+
+  // The number of bits needed to write the magnitude in binary.
+  pub fn bit_length(&self) -> u64 {
+    match self.magnitude.last() {
+      Some(top) => (self.magnitude.len() as u64 - 1) * 32 + (32 - top.leading_zeros()) as u64,
+      None => 0,
+    }
+  }
+
+  pub fn neg(&self) -> BigInt {
+    BigInt { negative: !self.negative, magnitude: self.magnitude.clone() }.normalized()
+  }
+
+  pub fn add(&self, other: &BigInt) -> BigInt {
+    if self.negative == other.negative {
+      return BigInt { negative: self.negative, magnitude: add_magnitudes(&self.magnitude, &other.magnitude) };
+    }
+    // Different signs: subtract the smaller magnitude from the larger one, which decides the sign.
+    match compare_magnitudes(&self.magnitude, &other.magnitude) {
+      Ordering::Less => BigInt { negative: other.negative, magnitude: sub_magnitudes(&other.magnitude, &self.magnitude) },
+      _ => BigInt { negative: self.negative, magnitude: sub_magnitudes(&self.magnitude, &other.magnitude) }.normalized(),
+    }
+  }
+
+  pub fn sub(&self, other: &BigInt) -> BigInt {
+    self.add(&other.neg())
+  }
+
+  pub fn mul(&self, other: &BigInt) -> BigInt {
+    let mut magnitude = vec![0u32; self.magnitude.len() + other.magnitude.len()];
+    for (i, a) in self.magnitude.iter().enumerate() {
+      let mut carry: u64 = 0;
+      for (j, b) in other.magnitude.iter().enumerate() {
+        let total = magnitude[i + j] as u64 + *a as u64 * *b as u64 + carry;
+        magnitude[i + j] = total as u32;
+        carry = total >> 32;
+      }
+      magnitude[i + other.magnitude.len()] = carry as u32;
+    }
+    BigInt { negative: self.negative != other.negative, magnitude }.normalized()
+  }
+
+  // Divide, rounding the quotient toward zero like Rust's integer division. The remainder takes the sign of
+  // `self`. Dividing by zero panics, the same as it does for built-in integers.
+  pub fn div_rem(&self, other: &BigInt) -> (BigInt, BigInt) {
+    if other.is_zero() {
+      panic!("attempt to divide by zero");
+    }
+    // Binary long division: bring down one bit of the dividend at a time.
+    let mut quotient = vec![0u32; self.magnitude.len()];
+    let mut remainder: Vec<u32> = vec![];
+    for i in (0..self.magnitude.len() * 32).rev() {
+      shift_left_one(&mut remainder);
+      if (self.magnitude[i / 32] >> (i % 32)) & 1 == 1 {
+        if remainder.is_empty() {
+          remainder.push(1);
+        } else {
+          remainder[0] |= 1;
+        }
+      }
+      if compare_magnitudes(&remainder, &other.magnitude) != Ordering::Less {
+        remainder = sub_magnitudes(&remainder, &other.magnitude);
+        quotient[i / 32] |= 1 << (i % 32);
+      }
+    }
+    let quotient = BigInt { negative: self.negative != other.negative, magnitude: quotient }.normalized();
+    let remainder = BigInt { negative: self.negative, magnitude: remainder }.normalized();
+    (quotient, remainder)
+  }
+
+  pub fn pow(&self, mut exponent: u32) -> BigInt {
+    let mut result = BigInt::from_i64(1);
+    let mut base = self.clone();
+    while exponent > 0 {
+      if exponent & 1 == 1 {
+        result = result.mul(&base);
+      }
+      exponent >>= 1;
+      if exponent > 0 {
+        base = base.mul(&base);
+      }
+    }
+    result
+  }
+
+  // Drop leading zero digits and make zero non-negative.
+  fn normalized(mut self) -> BigInt {
+    while self.magnitude.last() == Some(&0) {
+      self.magnitude.pop();
+    }
+    if self.magnitude.is_empty() {
+      self.negative = false;
+    }
+    self
+  }
+}
+
+impl Ord for BigInt {
+  fn cmp(&self, other: &BigInt) -> Ordering {
+    match (self.negative, other.negative) {
+      (false, true) => Ordering::Greater,
+      (true, false) => Ordering::Less,
+      (false, false) => compare_magnitudes(&self.magnitude, &other.magnitude),
+      (true, true) => compare_magnitudes(&other.magnitude, &self.magnitude),
+    }
+  }
+}
+
+impl PartialOrd for BigInt {
+  fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl fmt::Display for BigInt {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.is_zero() {
+      return write!(f, "0");
+    }
+    // Peel off nine decimal digits at a time, least significant chunk first.
+    let mut chunks = vec![];
+    let mut rest = self.magnitude.clone();
+    while !rest.is_empty() {
+      chunks.push(div_small(&mut rest, 1_000_000_000));
+    }
+    if self.negative {
+      write!(f, "-")?;
+    }
+    write!(f, "{}", chunks[chunks.len() - 1])?;
+    for chunk in chunks.iter().rev().skip(1) {
+      write!(f, "{:09}", chunk)?;
+    }
+    Ok(())
+  }
+}
+
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+  a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+  let mut result = vec![];
+  let mut carry: u64 = 0;
+  for i in 0..a.len().max(b.len()) {
+    let total = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+    result.push(total as u32);
+    carry = total >> 32;
+  }
+  if carry > 0 {
+    result.push(carry as u32);
+  }
+  result
+}
+
+// Subtract magnitudes where `a >= b`.
+fn sub_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+  let mut result = vec![];
+  let mut borrow: i64 = 0;
+  for (i, digit) in a.iter().enumerate() {
+    let mut difference = *digit as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+    borrow = 0;
+    if difference < 0 {
+      difference += 1 << 32;
+      borrow = 1;
+    }
+    result.push(difference as u32);
+  }
+  while result.last() == Some(&0) {
+    result.pop();
+  }
+  result
+}
+
+fn shift_left_one(magnitude: &mut Vec<u32>) {
+  let mut carry = 0;
+  for digit in magnitude.iter_mut() {
+    let next = *digit >> 31;
+    *digit = (*digit << 1) | carry;
+    carry = next;
+  }
+  if carry > 0 {
+    magnitude.push(carry);
+  }
+}
+
+// Compute `magnitude * factor + addend` in place.
+fn mul_small_add(magnitude: &mut Vec<u32>, factor: u32, addend: u32) {
+  let mut carry = addend as u64;
+  for digit in magnitude.iter_mut() {
+    let total = *digit as u64 * factor as u64 + carry;
+    *digit = total as u32;
+    carry = total >> 32;
+  }
+  if carry > 0 {
+    magnitude.push(carry as u32);
+  }
+}
+
+// Divide in place by a small divisor and return the remainder.
+fn div_small(magnitude: &mut Vec<u32>, divisor: u32) -> u32 {
+  let mut remainder: u64 = 0;
+  for digit in magnitude.iter_mut().rev() {
+    let current = (remainder << 32) | *digit as u64;
+    *digit = (current / divisor as u64) as u32;
+    remainder = current % divisor as u64;
+  }
+  while magnitude.last() == Some(&0) {
+    magnitude.pop();
+  }
+  remainder as u32
+}
@@ -0,0 +1,146 @@
+// Functions that are always available to scripts. A user-defined function with the same name takes precedence.
+use super::bigint::BigInt;
+use super::{big_value, RuntimeError, Value};
+
+// Check whether `name` is a builtin, so the caller knows whether to evaluate the arguments.
+pub fn exists(name: &str) -> bool {
+  matches!(name, "int" | "float" | "len" | "push" | "pop" | "slice" | "keys" | "values" | "contains" | "remove" |
+    "substring" | "split" | "join" | "trim" | "replace" | "upper" | "lower" | "to_string" | "parse_int" |
+    "is_some" | "is_none" | "unwrap" | "unwrap_or")
+}
+
+// Call the builtin `name` with already evaluated arguments.
+pub fn call(name: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+  match (name, args.as_slice()) {
+    // int(x) converts a number to an integer, truncating any fractional part toward zero.
+    ("int", [Value::Number(value)]) => Ok(Value::Number(*value)),
+    ("int", [Value::BigInt(value)]) => Ok(Value::BigInt(value.clone())),
+    ("int", [Value::Float(value)]) => {
+      match BigInt::from_f64(*value) {
+        Some(value) => Ok(big_value(value)),
+        None => Err(RuntimeError::NumberOutOfRange),
+      }
+    },
+    // float(x) converts a number to a float.
+    ("float", [Value::Number(value)]) => Ok(Value::Float(*value as f64)),
+    ("float", [Value::BigInt(value)]) => Ok(Value::Float(value.to_f64())),
+    ("float", [Value::Float(value)]) => Ok(Value::Float(*value)),
+    // len(list) returns the number of elements.
+    ("len", [Value::List(values)]) => Ok(Value::Number(values.borrow().len() as i64)),
+    // len(tuple) returns the number of elements.
+    ("len", [Value::Tuple(values)]) => Ok(Value::Number(values.len() as i64)),
+    // len(map) returns the number of entries.
+    ("len", [Value::Map(map)]) => Ok(Value::Number(map.borrow().len() as i64)),
+    // len(string) returns the number of characters.
+    ("len", [Value::String(text)]) => Ok(Value::Number(text.chars().count() as i64)),
+    // push(list, value) appends to the list in place and returns the new length.
+    ("push", [Value::List(values), value]) => {
+      let mut values = values.borrow_mut();
+      values.push(value.clone());
+      Ok(Value::Number(values.len() as i64))
+    },
+    // pop(list) removes and returns the last element.
+    ("pop", [Value::List(values)]) => {
+      match values.borrow_mut().pop() {
+        Some(value) => Ok(value),
+        None => Err(RuntimeError::PopFromEmptyList),
+      }
+    },
+    // slice(list, start, end) returns a new list with the elements from start up to, but not including, end.
+    ("slice", [Value::List(values), Value::Number(start), Value::Number(end)]) => {
+      let values = values.borrow();
+      check_range(*start, *end, values.len())?;
+      Ok(Value::list(values[*start as usize..*end as usize].to_vec()))
+    },
+    // keys(map) and values(map) return lists in insertion order.
+    ("keys", [Value::Map(map)]) => Ok(Value::list(map.borrow().keys())),
+    ("values", [Value::Map(map)]) => Ok(Value::list(map.borrow().values())),
+    // contains(map, key) checks whether the key is present.
+    ("contains", [Value::Map(map), key]) => Ok(Value::Bool(map.borrow().get(key).is_some())),
+    // contains(list, value) checks whether any element equals the value.
+    ("contains", [Value::List(values), value]) => Ok(Value::Bool(values.borrow().contains(value))),
+    // contains(string, text) checks whether the text occurs in the string.
+    ("contains", [Value::String(text), Value::String(part)]) => Ok(Value::Bool(text.contains(part.as_str()))),
+    // remove(map, key) deletes the entry and returns its value.
+    ("remove", [Value::Map(map), key]) => {
+      match map.borrow_mut().remove(key) {
+        Some(value) => Ok(value),
+        None => Err(RuntimeError::KeyNotFound { key: key.to_string() }),
+      }
+    },
+    // substring(string, start, end) returns the characters from start up to, but not including, end.
+    ("substring", [Value::String(text), Value::Number(start), Value::Number(end)]) => {
+      check_range(*start, *end, text.chars().count())?;
+      Ok(Value::String(text.chars().skip(*start as usize).take((end - start) as usize).collect()))
+    },
+    // split(string, separator) returns a list of the pieces between separators. An empty separator splits
+    // the string into its characters.
+    ("split", [Value::String(text), Value::String(separator)]) => {
+      let pieces: Vec<Value> = if separator.is_empty() {
+        text.chars().map(|c| Value::String(c.to_string())).collect()
+      } else {
+        text.split(separator.as_str()).map(|piece| Value::String(piece.to_string())).collect()
+      };
+      Ok(Value::list(pieces))
+    },
+    // join(list, separator) joins a list of strings with the separator between them.
+    ("join", [Value::List(values), Value::String(separator)]) => {
+      let mut pieces = vec![];
+      for value in values.borrow().iter() {
+        match value {
+          Value::String(piece) => pieces.push(piece.clone()),
+          value => return Err(RuntimeError::ExpectedString { found: value.type_name() }),
+        }
+      }
+      Ok(Value::String(pieces.join(separator)))
+    },
+    // trim(string) removes whitespace from both ends.
+    ("trim", [Value::String(text)]) => Ok(Value::String(text.trim().to_string())),
+    // replace(string, from, to) replaces every occurrence of from.
+    ("replace", [Value::String(text), Value::String(from), Value::String(to)]) => {
+      if from.is_empty() {
+        return Err(RuntimeError::EmptySearchString);
+      }
+      Ok(Value::String(text.replace(from.as_str(), to)))
+    },
+    // upper(string) and lower(string) change the case of every letter.
+    ("upper", [Value::String(text)]) => Ok(Value::String(text.to_uppercase())),
+    ("lower", [Value::String(text)]) => Ok(Value::String(text.to_lowercase())),
+    // to_string(x) formats any value the way it is displayed.
+    ("to_string", [value]) => Ok(Value::String(value.to_string())),
+    // parse_int(string) reads a decimal integer, ignoring surrounding whitespace.
+    ("parse_int", [Value::String(text)]) => {
+      let text = text.trim();
+      match BigInt::parse(text.strip_prefix('+').filter(|rest| !rest.starts_with('-')).unwrap_or(text)) {
+        Some(value) => Ok(big_value(value)),
+        None => Err(RuntimeError::InvalidInteger { text: text.to_string() }),
+      }
+    },
+    // is_some(option) and is_none(option) check which `Option` variant a value is.
+    ("is_some", [Value::Variant(variant)]) if variant.enum_name == "Option" => Ok(Value::Bool(variant.name == "Some")),
+    ("is_none", [Value::Variant(variant)]) if variant.enum_name == "Option" => Ok(Value::Bool(variant.name == "None")),
+    // unwrap(option) returns the value inside `Some`, and fails on `None`.
+    ("unwrap", [Value::Variant(variant)]) if variant.enum_name == "Option" => {
+      match variant.fields.first() {
+        Some(value) => Ok(value.clone()),
+        None => Err(RuntimeError::UnwrapNone),
+      }
+    },
+    // unwrap_or(option, default) returns the value inside `Some`, or the default for `None`.
+    ("unwrap_or", [Value::Variant(variant), default]) if variant.enum_name == "Option" => {
+      Ok(variant.fields.first().unwrap_or(default).clone())
+    },
+    _ => Err(RuntimeError::InvalidArguments { name: name.to_string() }),
+  }
+}
+
+// Check that `start..end` is a valid range into a sequence of `len` elements, reporting the first bad bound.
+fn check_range(start: i64, end: i64, len: usize) -> Result<(), RuntimeError> {
+  if start < 0 || start > end {
+    return Err(RuntimeError::IndexOutOfBounds { index: start, len });
+  }
+  if end > len as i64 {
+    return Err(RuntimeError::IndexOutOfBounds { index: end, len });
+  }
+  Ok(())
+}
@@ -0,0 +1,212 @@
+// Errors a script can fail with at runtime. `RuntimeError` says what went wrong, and `Error` adds where.
+use super::Value;
+use crate::parser::Span;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+  UndefinedVariable { name: String },
+  UndefinedFunction { name: String },
+  UndefinedStruct { name: String },
+  UndefinedVariant { name: String },
+  NotAFunction { found: &'static str },
+  // An operator applied to values it doesn't support, e.g. `"a" - 1`.
+  TypeMismatch { op: String, lhs: &'static str, rhs: &'static str },
+  InvalidOperand { op: String, found: &'static str },
+  UnknownOperator { op: String },
+  // Bitwise operators work on the 64-bit two's complement form, which bignums don't have.
+  IntegerTooLarge { op: String },
+  ArityMismatch { name: String, expected: usize, found: usize },
+  MissingArgument { function: String, name: String },
+  UnknownArgument { function: String, name: String },
+  DuplicateArgument { function: String, name: String },
+  // A parameter without a default after one with a default, e.g. `b` in `fn f(a = 1, b)`.
+  RequiredAfterDefault { name: String },
+  InvalidArguments { name: String },
+  // A call nested deeper than the runtime allows, usually runaway recursion. `depth` is the number of calls that
+  // were already running. Calls made by `return f(...)` don't nest, so recursion through them never overflows.
+  StackOverflow { function: String, depth: usize },
+  // A call that would take the host stack past the runtime's byte budget, `limit`. Deep recursion through heavy
+  // expressions can hit this before the depth limit.
+  StackExhausted { function: String, limit: usize },
+  // A script that ran longer than the step limit set with `Runtime::with_max_steps`.
+  StepLimitExceeded { limit: usize },
+  DivisionByZero,
+  IntegerOverflow,
+  NegativeExponent,
+  NegativeShift,
+  NumberOutOfRange,
+  InvalidInteger { text: String },
+  IndexOutOfBounds { index: i64, len: usize },
+  // An index too big for an `i64`, e.g. `xs[2^70]`, which can't be in range for any list.
+  IndexOutOfRange { index: String, len: usize },
+  InvalidIndex { found: &'static str },
+  NotIndexable { found: &'static str },
+  KeyNotFound { key: String },
+  InvalidMapKey { found: &'static str },
+  UnknownField { name: String },
+  MissingField { name: String },
+  InvalidFieldAccess { field: String, found: &'static str },
+  NotIterable { found: &'static str },
+  ExpectedBool { found: &'static str },
+  ExpectedString { found: &'static str },
+  EmptySearchString,
+  PopFromEmptyList,
+  UnwrapNone,
+  PatternMismatch,
+  NonExhaustiveMatch,
+  NoMatchingArm,
+  InvalidAssignmentTarget,
+  // A syntax tree the runtime doesn't know how to evaluate.
+  InvalidNode { node: &'static str },
+  // A value passed to `throw` that no `catch` handled.
+  Thrown { value: Value },
+}
+
+impl fmt::Display for RuntimeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      RuntimeError::UndefinedVariable { name } => write!(f, "Undefined variable `{}`", name),
+      RuntimeError::UndefinedFunction { name } => write!(f, "Undefined function `{}`", name),
+      RuntimeError::UndefinedStruct { name } => write!(f, "Undefined struct `{}`", name),
+      RuntimeError::UndefinedVariant { name } => write!(f, "Undefined variant `{}`", name),
+      RuntimeError::NotAFunction { found } => write!(f, "A {} is not a function", found),
+      RuntimeError::TypeMismatch { op, lhs, rhs } => write!(f, "Cannot apply `{}` to {} and {}", op, lhs, rhs),
+      RuntimeError::InvalidOperand { op, found } => write!(f, "Cannot apply `{}` to {}", op, found),
+      RuntimeError::UnknownOperator { op } => write!(f, "Unknown operator `{}`", op),
+      RuntimeError::IntegerTooLarge { op } => write!(f, "`{}` needs 64-bit integers", op),
+      RuntimeError::ArityMismatch { name, expected, found } => {
+        write!(f, "`{}` takes {} argument{} but {} were given", name, expected, if *expected == 1 { "" } else { "s" }, found)
+      },
+      RuntimeError::MissingArgument { function, name } => write!(f, "Missing argument `{}` in call to `{}`", name, function),
+      RuntimeError::UnknownArgument { function, name } => write!(f, "`{}` has no parameter `{}`", function, name),
+      RuntimeError::DuplicateArgument { function, name } => write!(f, "Argument `{}` given twice in call to `{}`", name, function),
+      RuntimeError::RequiredAfterDefault { name } => {
+        write!(f, "Required parameter `{}` follows a parameter with a default value", name)
+      },
+      RuntimeError::InvalidArguments { name } => write!(f, "Invalid arguments to `{}`", name),
+      RuntimeError::StackOverflow { function, depth } => {
+        write!(f, "Stack overflow: call to `{}` with {} calls already running", function, depth)
+      },
+      RuntimeError::StackExhausted { function, limit } => {
+        write!(f, "Stack overflow: call to `{}` would use more than {} bytes of stack", function, limit)
+      },
+      RuntimeError::StepLimitExceeded { limit } => write!(f, "Step limit exceeded: the script ran for more than {} steps", limit),
+      RuntimeError::DivisionByZero => write!(f, "Division by zero"),
+      RuntimeError::IntegerOverflow => write!(f, "Integer overflow"),
+      RuntimeError::NegativeExponent => write!(f, "Negative exponent"),
+      RuntimeError::NegativeShift => write!(f, "Negative shift amount"),
+      RuntimeError::NumberOutOfRange => write!(f, "Number out of range"),
+      RuntimeError::InvalidInteger { text } => write!(f, "Invalid integer {:?}", text),
+      RuntimeError::IndexOutOfBounds { index, len } => write!(f, "Index {} out of bounds for length {}", index, len),
+      RuntimeError::IndexOutOfRange { index, len } => write!(f, "Index {} out of bounds for length {}", index, len),
+      RuntimeError::InvalidIndex { found } => write!(f, "Expected an int index but found a {}", found),
+      RuntimeError::NotIndexable { found } => write!(f, "A {} cannot be indexed", found),
+      RuntimeError::KeyNotFound { key } => write!(f, "Key {} not found", key),
+      RuntimeError::InvalidMapKey { found } => write!(f, "A {} cannot be a map key", found),
+      RuntimeError::UnknownField { name } => write!(f, "Unknown field `{}`", name),
+      RuntimeError::MissingField { name } => write!(f, "Missing field `{}`", name),
+      RuntimeError::InvalidFieldAccess { field, found } => write!(f, "A {} has no field `{}`", found, field),
+      RuntimeError::NotIterable { found } => write!(f, "A {} is not iterable", found),
+      RuntimeError::ExpectedBool { found } => write!(f, "Expected a bool but found a {}", found),
+      RuntimeError::ExpectedString { found } => write!(f, "Expected a string but found a {}", found),
+      RuntimeError::EmptySearchString => write!(f, "Empty search string"),
+      RuntimeError::PopFromEmptyList => write!(f, "Pop from empty list"),
+      RuntimeError::UnwrapNone => write!(f, "Unwrap of None"),
+      RuntimeError::PatternMismatch => write!(f, "Value does not match the let pattern"),
+      RuntimeError::NonExhaustiveMatch => write!(f, "Non-exhaustive match"),
+      RuntimeError::NoMatchingArm => write!(f, "No match arm matched"),
+      RuntimeError::InvalidAssignmentTarget => write!(f, "Invalid assignment target"),
+      RuntimeError::InvalidNode { node } => write!(f, "Cannot evaluate {}", node),
+      RuntimeError::Thrown { value: Value::String(text) } => write!(f, "Uncaught exception: {}", text),
+      RuntimeError::Thrown { value } => write!(f, "Uncaught exception: {}", value),
+    }
+  }
+}
+
+impl std::error::Error for RuntimeError {}
+
+// A runtime error together with the location of the statement or call that raised it, and the
+// function calls that were active at the time, innermost first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+  pub error: RuntimeError,
+  pub span: Option<Span>,
+  pub trace: Vec<Frame>,
+}
+
+// One line of a backtrace: the function that was running and where it was in the source. `elided` counts the
+// functions just outside it that tail calls replaced, which the backtrace no longer has.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+  pub function: String,
+  pub span: Span,
+  pub elided: usize,
+}
+
+impl Error {
+  // Record where the error happened, unless a more precise location is already known.
+  pub fn at(mut self, span: Span) -> Error {
+    if self.span.is_none() {
+      self.span = Some(span);
+    }
+    self
+  }
+
+  // Format the error and its backtrace the way the command line reports it, e.g.
+  //
+  //   error: Division by zero
+  //     at bar (script.asa:6:11)
+  //     at main (script.asa:9:3)
+  pub fn report(&self, file: &str, source: &str) -> String {
+    let mut text = format!("error: {}", self.error);
+    // Runaway recursion leaves the same frame many times over; print it once with a count.
+    let mut frames = self.trace.iter().peekable();
+    while let Some(frame) = frames.next() {
+      let (line, column) = frame.span.line_col(source);
+      text.push_str(&format!("\n  at {} ({}:{}:{})", frame.function, file, line, column));
+      let mut repeats = 0;
+      while frames.next_if_eq(&frame).is_some() {
+        repeats += 1;
+      }
+      if repeats > 0 {
+        text.push_str(&format!("\n  ... repeated {} more time{}", repeats, if repeats == 1 { "" } else { "s" }));
+      }
+      if frame.elided > 0 {
+        text.push_str(&format!("\n  ... {} more frame{} replaced by tail calls", frame.elided, if frame.elided == 1 { "" } else { "s" }));
+      }
+    }
+    // Errors found before the script runs have a location but no calls.
+    if let (true, Some(span)) = (self.trace.is_empty(), self.span) {
+      let (line, column) = span.line_col(source);
+      text.push_str(&format!("\n  at {}:{}:{}", file, line, column));
+    }
+    text
+  }
+}
+
+impl From<RuntimeError> for Error {
+  fn from(error: RuntimeError) -> Error {
+    Error { error, span: None, trace: vec![] }
+  }
+}
+
+// The interpreter passes errors up boxed. A `Result` holding a whole `Error` is several times the size of a
+// `Value`, and the temporaries it needs fill the stack frames of the functions that recurse for each call.
+impl From<RuntimeError> for Box<Error> {
+  fn from(error: RuntimeError) -> Box<Error> {
+    Box::new(Error::from(error))
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.error)
+  }
+}
+
+impl std::error::Error for Error {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    Some(&self.error)
+  }
+}
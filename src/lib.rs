@@ -10,6 +10,13 @@ pub enum Token {
   Equal(u8),
   Plus(u8),
   Dash(u8),
+  Slash(u8), // also doubled for floor division: //
+  Percent(u8),
+  Ampersand(u8),
+  Pipe(u8),
+  Tilde(u8),
+  LeftAngle(u8), // also doubled for shift left: <<
+  RightAngle(u8), // also doubled for shift right: >>
   Quote(u8),
   WhiteSpace(u8), //space, \t, \r, \n
   Semicolon(u8),
@@ -21,8 +28,10 @@ pub enum Token {
 extern crate nom;
 
 mod parser;
+mod interpreter;
 
-pub use self::parser::{math_expression, Node};
+pub use self::parser::{math_expression, program, Node, Span};
+pub use self::interpreter::{Value, Error, Frame, RuntimeError, Runtime, DEFAULT_MAX_CALL_DEPTH, DEFAULT_MAX_STACK_SIZE, start_interpreter};
 
 // lex function should iterate over every byte of the input string, 
 // and categorize each one according to the token descriptions above.
@@ -74,7 +83,7 @@ pub fn lex(input: &str) -> Vec<Token> {
       }
       else {
         tokens.push(Token::Alpha(bytes[counter]));
-        counter = counter + 1;
+        counter += 1;
       }
     }
     else {
@@ -90,6 +99,13 @@ pub fn lex(input: &str) -> Vec<Token> {
         0x3D => Token::Equal(bytes[counter]),
         0x2B => Token::Plus(bytes[counter]),
         0x2D => Token::Dash(bytes[counter]),
+        0x2F => Token::Slash(bytes[counter]),
+        0x25 => Token::Percent(bytes[counter]),
+        0x26 => Token::Ampersand(bytes[counter]),
+        0x7C => Token::Pipe(bytes[counter]),
+        0x7E => Token::Tilde(bytes[counter]),
+        0x3C => Token::LeftAngle(bytes[counter]),
+        0x3E => Token::RightAngle(bytes[counter]),
         0x22 => Token::Quote(bytes[counter]),
         0x20 | 0x0D | 0x09 | 0x0A => Token::WhiteSpace(bytes[counter]), // space, cr, tab, new line
         0x3B => Token::Semicolon(bytes[counter]),
@@ -97,12 +113,11 @@ pub fn lex(input: &str) -> Vec<Token> {
         _ => Token::Other(),
       };
       tokens.push(token);
-      counter = counter + 1;
+      counter += 1;
     }
   }
   tokens.push(Token::EOF);
-  println!("{:#?}", tokens);
-  return tokens;
+  tokens
 }
 
 pub fn strip_whitespace(tokens: &Vec<Token>) -> Vec<Token> {
@@ -113,6 +128,6 @@ pub fn strip_whitespace(tokens: &Vec<Token>) -> Vec<Token> {
           _ => new_tokens.push(token.clone()),
       }
   }
-  return new_tokens
+  new_tokens
 }
 
@@ -1,19 +1,58 @@
-extern crate nom;
-extern crate asalang_parser;
+extern crate asalang;
 
-use asalang_parser::{math_expression, Node};
+use asalang::{program, Runtime, Span};
+use std::process::ExitCode;
 
-fn main() -> Result<(), nom::Err<(&'static str, nom::error::ErrorKind)>> {
-  let result = math_expression(r#"4+2"#);
-  match result {
-    Ok((unparsed,tree)) => {
-      println!("Unparsed Text: {:?}", unparsed);
-      println!("Parse Tree:\n {:?}", tree);
+// Scripts run on their own thread with a far bigger stack than the defaults assume, so they may nest calls more
+// deeply. Scripts may use half of it, leaving the rest as a safety margin.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+const MAX_CALL_DEPTH: usize = 10_000;
+
+// Run a script file and print the value it produces, e.g. `asalang script.asa`.
+fn main() -> ExitCode {
+  let file = match std::env::args().nth(1) {
+    Some(file) => file,
+    None => {
+      eprintln!("usage: asalang <script>");
+      return ExitCode::FAILURE;
+    }
+  };
+  let source = match std::fs::read_to_string(&file) {
+    Ok(source) => source,
+    Err(error) => {
+      eprintln!("error: cannot read {}: {}", file, error);
+      return ExitCode::FAILURE;
+    }
+  };
+  let tree = match program(&source) {
+    Ok(("", tree)) => tree,
+    // Point at the first character the parser could not consume.
+    Ok((unparsed, _)) => {
+      let (line, column) = Span { start: source.len() - unparsed.len(), end: source.len() }.line_col(&source);
+      eprintln!("error: unexpected input\n  at {}:{}:{}", file, line, column);
+      return ExitCode::FAILURE;
+    }
+    Err(error) => {
+      eprintln!("error: cannot parse {}: {:?}", file, error);
+      return ExitCode::FAILURE;
+    }
+  };
+  let interpreter = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(move || match Runtime::new().with_max_call_depth(MAX_CALL_DEPTH).with_max_stack_size(STACK_SIZE / 2).start(&tree) {
+    Ok(value) => {
+      println!("{}", value);
+      ExitCode::SUCCESS
+    }
+    Err(error) => {
+      eprintln!("{}", error.report(&file, &source));
+      ExitCode::FAILURE
     }
+  });
+  match interpreter.map(|thread| thread.join()) {
+    Ok(Ok(code)) => code,
+    Ok(Err(_)) => ExitCode::FAILURE,
     Err(error) => {
-      println!("ERROR {:?}", error);
+      eprintln!("error: cannot start the interpreter: {}", error);
+      ExitCode::FAILURE
     }
   }
-    
-  Ok(())
 }
@@ -4,20 +4,49 @@
 use nom::{
   IResult,
   branch::alt,
-  combinator::opt, 
-  multi::{many1, many0},
-  bytes::complete::{tag},
-  character::complete::{alphanumeric1, digit1},
+  combinator::{cut, map, map_opt, not, opt, recognize, value, verify},
+  sequence::{delimited, preceded, terminated, tuple},
+  multi::{fold_many0, many1, many0, separated_list0, separated_list1},
+  bytes::complete::{is_not, tag, take_until, take_while_m_n},
+  character::complete::{alphanumeric1, digit1, multispace0},
 };
+// A range of byte offsets into the script source, used to say where a runtime error happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+  pub start: usize,
+  pub end: usize,
+}
+impl Span {
+  // While parsing, a span holds the length of the input left at its start and end, because the parsers only
+  // see the rest of the input. `program` turns those into offsets once the whole source length is known.
+  fn remaining(start: &str, end: &str) -> Span {
+    Span { start: start.len(), end: end.len() }
+  }
+  // The 1-based line and column of the start of the span.
+  pub fn line_col(&self, source: &str) -> (usize, usize) {
+    let before = &source[..self.start.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, column)
+  }
+}
 // Here are the different node types. You will use these to make your parser and your grammar.
 // You may add other nodes as you see fit, but these are expected by the runtime.
 #[derive(Debug, Clone)]
 pub enum Node {
   Program { children: Vec<Node> },
-  Statement { children: Vec<Node> },
+  Statement { children: Vec<Node>, span: Span },
   FunctionReturn { children: Vec<Node> },
   FunctionDefine { children: Vec<Node> },
   FunctionArguments { children: Vec<Node> },
+  // A parameter of a function definition, with its default value as the child if it has one.
+  Parameter { name: String, children: Vec<Node> },
+  // A last parameter that collects any remaining arguments into a list, e.g. `...xs`.
+  RestParameter { name: String },
+  // An argument passed by parameter name, e.g. `port: 80`.
+  NamedArgument { name: String, children: Vec<Node> },
+  // A list argument whose elements are passed as separate arguments, e.g. `...xs`.
+  Spread { children: Vec<Node> },
   FunctionStatements { children: Vec<Node> },
   Expression { children: Vec<Node> },
   IfExpression { children: Vec<Node> },
@@ -26,54 +55,423 @@ pub enum Node {
   ElseBranch { children: Vec<Node> },
   ComparisonExpression { name: String, children: Vec<Node> },
   MathExpression {name: String, children: Vec<Node> },
-  FunctionCall { name: String, children: Vec<Node> },
+  UnaryExpression { name: String, children: Vec<Node> },
+  FunctionCall { name: String, children: Vec<Node>, span: Span },
+  FunctionLiteral { children: Vec<Node> },
+  Call { children: Vec<Node>, span: Span },
+  List { children: Vec<Node> },
+  Index { children: Vec<Node> },
+  Assignment { children: Vec<Node> },
+  Map { children: Vec<Node> },
+  MapEntry { children: Vec<Node> },
+  ForLoop { children: Vec<Node> },
+  TryCatch { children: Vec<Node> },
+  Block { children: Vec<Node> },
+  Throw { children: Vec<Node> },
+  StructDefine { name: String, children: Vec<Node> },
+  StructLiteral { name: String, children: Vec<Node> },
+  FieldInit { name: String, children: Vec<Node> },
+  FieldAccess { name: String, children: Vec<Node> },
+  EnumDefine { name: String, children: Vec<Node> },
+  EnumVariant { name: String, children: Vec<Node> },
+  MatchExpression { children: Vec<Node> },
+  MatchArm { children: Vec<Node> },
+  VariantPattern { name: String, children: Vec<Node> },
+  ListPattern { children: Vec<Node> },
+  Tuple { children: Vec<Node> },
+  TuplePattern { children: Vec<Node> },
+  StructPattern { name: String, children: Vec<Node> },
+  Wildcard,
   VariableDefine { children: Vec<Node> },
-  Number { value: i32 },
+  Number { value: i64 },
+  BigNumber { value: String },
+  Float { value: f64 },
   Bool { value: bool },
   Identifier { value: String },
   String { value: String },
+  Interpolation { children: Vec<Node> },
+}
+impl Node {
+  // The child nodes of any node, so passes over the whole tree don't need to match every variant.
+  pub fn children(&self) -> &[Node] {
+    match self {
+      Node::Number { .. } |
+      Node::BigNumber { .. } |
+      Node::Float { .. } |
+      Node::Bool { .. } |
+      Node::Identifier { .. } |
+      Node::RestParameter { .. } |
+      Node::String { .. } |
+      Node::Wildcard => &[],
+      Node::Program { children } |
+      Node::Statement { children, .. } |
+      Node::FunctionReturn { children } |
+      Node::FunctionDefine { children } |
+      Node::FunctionArguments { children } |
+      Node::Parameter { children, .. } |
+      Node::NamedArgument { children, .. } |
+      Node::Spread { children } |
+      Node::FunctionStatements { children } |
+      Node::Expression { children } |
+      Node::IfExpression { children } |
+      Node::IfBranch { children } |
+      Node::ElifBranch { children } |
+      Node::ElseBranch { children } |
+      Node::ComparisonExpression { children, .. } |
+      Node::MathExpression { children, .. } |
+      Node::UnaryExpression { children, .. } |
+      Node::FunctionCall { children, .. } |
+      Node::FunctionLiteral { children } |
+      Node::Call { children, .. } |
+      Node::List { children } |
+      Node::Index { children } |
+      Node::Assignment { children } |
+      Node::Map { children } |
+      Node::MapEntry { children } |
+      Node::ForLoop { children } |
+      Node::TryCatch { children } |
+      Node::Block { children } |
+      Node::Throw { children } |
+      Node::StructDefine { children, .. } |
+      Node::StructLiteral { children, .. } |
+      Node::FieldInit { children, .. } |
+      Node::FieldAccess { children, .. } |
+      Node::EnumDefine { children, .. } |
+      Node::EnumVariant { children, .. } |
+      Node::MatchExpression { children } |
+      Node::MatchArm { children } |
+      Node::VariantPattern { children, .. } |
+      Node::ListPattern { children } |
+      Node::Tuple { children } |
+      Node::Interpolation { children } |
+      Node::TuplePattern { children } |
+      Node::StructPattern { children, .. } |
+      Node::VariableDefine { children } => children,
+    }
+  }
+
+  pub fn children_mut(&mut self) -> &mut [Node] {
+    match self {
+      Node::Number { .. } |
+      Node::BigNumber { .. } |
+      Node::Float { .. } |
+      Node::Bool { .. } |
+      Node::Identifier { .. } |
+      Node::RestParameter { .. } |
+      Node::String { .. } |
+      Node::Wildcard => &mut [],
+      Node::Program { children } |
+      Node::Statement { children, .. } |
+      Node::FunctionReturn { children } |
+      Node::FunctionDefine { children } |
+      Node::FunctionArguments { children } |
+      Node::Parameter { children, .. } |
+      Node::NamedArgument { children, .. } |
+      Node::Spread { children } |
+      Node::FunctionStatements { children } |
+      Node::Expression { children } |
+      Node::IfExpression { children } |
+      Node::IfBranch { children } |
+      Node::ElifBranch { children } |
+      Node::ElseBranch { children } |
+      Node::ComparisonExpression { children, .. } |
+      Node::MathExpression { children, .. } |
+      Node::UnaryExpression { children, .. } |
+      Node::FunctionCall { children, .. } |
+      Node::FunctionLiteral { children } |
+      Node::Call { children, .. } |
+      Node::List { children } |
+      Node::Index { children } |
+      Node::Assignment { children } |
+      Node::Map { children } |
+      Node::MapEntry { children } |
+      Node::ForLoop { children } |
+      Node::TryCatch { children } |
+      Node::Block { children } |
+      Node::Throw { children } |
+      Node::StructDefine { children, .. } |
+      Node::StructLiteral { children, .. } |
+      Node::FieldInit { children, .. } |
+      Node::FieldAccess { children, .. } |
+      Node::EnumDefine { children, .. } |
+      Node::EnumVariant { children, .. } |
+      Node::MatchExpression { children } |
+      Node::MatchArm { children } |
+      Node::VariantPattern { children, .. } |
+      Node::ListPattern { children } |
+      Node::Tuple { children } |
+      Node::Interpolation { children } |
+      Node::TuplePattern { children } |
+      Node::StructPattern { children, .. } |
+      Node::VariableDefine { children } => children,
+    }
+  }
+}
+// Ebnf: name = (alnum | "_")+ ;
+pub fn name(input: &str) -> IResult<&str, &str> {
+  recognize(many1(alt((alphanumeric1, tag("_")))))(input)
 }
 // Define production rules for an identifier
 pub fn identifier(input: &str) -> IResult<&str, Node> {
-  let (input, result) = alphanumeric1(input)?;              // Consume at least 1 alphanumeric character. The ? automatically unwraps the result if it's okay and bails if it is an error.
+  let (input, result) = name(input)?;                       // Consume at least 1 alphanumeric character or underscore. The ? automatically unwraps the result if it's okay and bails if it is an error.
   Ok((input, Node::Identifier{ value: result.to_string()})) // Return the now partially consumed input, as well as a node with the string on it.
 }
 // Define an integer number
 pub fn number(input: &str) -> IResult<&str, Node> {
   let (input, result) = digit1(input)?;                     // Consume at least 1 digit 0-9
-  let number = result.parse::<i32>().unwrap();              // Parse the string result into a usize
-  Ok((input, Node::Number{ value: number}))                 // Return the now partially consumed input with a number as well
+  match result.parse::<i64>() {                             // Parse the string result into an i64
+    Ok(number) => Ok((input, Node::Number{ value: number})), // Return the now partially consumed input with a number as well
+    Err(_) => Ok((input, Node::BigNumber{ value: result.to_string()})), // Too large for an i64, so the runtime makes it a bignum
+  }
+}
+// Define a floating-point number: digits with a fractional part, an exponent, or both, e.g. `1.5`, `2e3` or `2.5e-3`
+pub fn float(input: &str) -> IResult<&str, Node> {
+  let fraction = tuple((tag("."), digit1));
+  let (input, result) = recognize(tuple((digit1, alt((recognize(tuple((fraction, opt(exponent)))), exponent)))))(input)?;
+  let number = result.parse::<f64>().unwrap();              // The literal was already validated above, so parsing can't fail
+  Ok((input, Node::Float{ value: number }))
+}
+// The exponent of a float literal, e.g. `e3` or `E-7`
+pub fn exponent(input: &str) -> IResult<&str, &str> {
+  recognize(tuple((alt((tag("e"), tag("E"))), opt(alt((tag("+"), tag("-")))), digit1)))(input)
 }
 pub fn boolean(input: &str) -> IResult<&str, Node> {
   let (input, result) = alt((tag("true"),tag("false")))(input)?;
-  let bool_value = if result == "true" {true} else {false};
+  let bool_value = result == "true";
   Ok((input, Node::Bool{ value: bool_value}))
 }
+// Ebnf: string = raw_string | "\"" , { string_fragment } , "\"" ;
+// A string may span several lines; the newlines are kept as part of its value. A string with `{expression}`
+// parts becomes an `Interpolation` of its text and expressions, in order.
 pub fn string(input: &str) -> IResult<&str, Node> {
+  if let Ok(result) = raw_string(input) {
+    return Ok(result);
+  }
   let (input, _) = tag("\"")(input)?;
-  let (input, string) = many1(alt((alphanumeric1,tag(" "))))(input)?;
+  let (input, (mut parts, text)) = fold_many0(string_fragment, || (vec![], String::new()), |(mut parts, mut text), fragment| {
+    match fragment {
+      StringFragment::Literal(literal) => text.push_str(literal),
+      StringFragment::Escaped(c) => text.push(c),
+      StringFragment::Skipped => (),
+      StringFragment::Interpolated(expression) => {
+        if !text.is_empty() {
+          parts.push(Node::String{ value: std::mem::take(&mut text) });
+        }
+        parts.push(expression);
+      }
+    }
+    (parts, text)
+  })(input)?;
   let (input, _) = tag("\"")(input)?;
-  Ok((input, Node::String{ value: string.join("")}))
+  if parts.is_empty() {
+    return Ok((input, Node::String{ value: text }));
+  }
+  if !text.is_empty() {
+    parts.push(Node::String{ value: text });
+  }
+  Ok((input, Node::Interpolation{ children: parts }))
+}
+// The pieces a string literal is built from.
+pub enum StringFragment<'a> {
+  Literal(&'a str),
+  Escaped(char),
+  Skipped,
+  Interpolated(Node),
+}
+// Ebnf: string_fragment = (any character except "\"" , "\\" or "{")+ | escape | "\\" , newline , {whitespace} | interpolation ;
+pub fn string_fragment(input: &str) -> IResult<&str, StringFragment<'_>> {
+  alt((
+    map(is_not("\"\\{"), StringFragment::Literal),
+    // A backslash at the end of a line joins it to the next one, dropping the leading indentation.
+    map(tuple((tag("\\"), opt(tag("\r")), tag("\n"), multispace0)), |_| StringFragment::Skipped),
+    map(escape, StringFragment::Escaped),
+    map(interpolation, StringFragment::Interpolated),
+  ))(input)
+}
+// Ebnf: interpolation = "{" , expression , "}" ;
+// Once a "{" is seen the expression and closing "}" are required, so a typo is reported instead of ending the string.
+pub fn interpolation(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("{")(input)?;
+  cut(delimited(whitespace, expression, tuple((whitespace, tag("}")))))(input)
+}
+// Ebnf: escape = "\\" , ("n" | "t" | "r" | "0" | "\"" | "\\" | "{" | "}" | "u{" , hex_digit , {hex_digit} , "}") ;
+// Anything else after a backslash is a syntax error rather than the end of the string.
+pub fn escape(input: &str) -> IResult<&str, char> {
+  preceded(tag("\\"), cut(alt((
+    value('\n', tag("n")),
+    value('\t', tag("t")),
+    value('\r', tag("r")),
+    value('\0', tag("0")),
+    value('"', tag("\"")),
+    value('\\', tag("\\")),
+    value('{', tag("{")),
+    value('}', tag("}")),
+    unicode_escape,
+  ))))(input)
+}
+// Ebnf: unicode_escape = "u{" , hex_digit , {hex_digit} , "}" ;
+// At most six hex digits, naming a valid Unicode scalar value.
+pub fn unicode_escape(input: &str) -> IResult<&str, char> {
+  map_opt(
+    delimited(tag("u{"), take_while_m_n(1, 6, |c: char| c.is_ascii_hexdigit()), tag("}")),
+    |hex| u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+  )(input)
+}
+// Ebnf: raw_string = "r" , {"#"} , "\"" , {any character} , "\"" , {"#"} ;
+// Nothing is escaped in a raw string. It ends at the first quote followed by as many "#" as it started with.
+pub fn raw_string(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("r")(input)?;
+  let (input, hashes) = recognize(many0(tag("#")))(input)?;
+  let (input, _) = tag("\"")(input)?;
+  let closing = format!("\"{}", hashes);
+  let (input, value) = take_until(closing.as_str())(input)?;
+  let (input, _) = tag(closing.as_str())(input)?;
+  Ok((input, Node::String{ value: value.to_string() }))
 }
 pub fn function_call(input: &str) -> IResult<&str, Node> {
-  let (input, name) = alphanumeric1(input)?;
+  let start = input;
+  let (input, name) = name(input)?;
   let (input, _) = tag("(")(input)?;
-  let (input, mut args) = many0(arguments)(input)?;
+  let (input, args) = many0(call_arguments)(input)?;
   let (input, _) = tag(")")(input)?;
-  Ok((input, Node::FunctionCall{name: name.to_string(), children: args}))   
+  Ok((input, Node::FunctionCall{name: name.to_string(), children: args, span: Span::remaining(start, input)}))
 }
 pub fn parenthetical_expression(input: &str) -> IResult<&str, Node> {
   let (input, _) = many0(tag(" "))(input)?;
   let (input, _) = tag("(")(input)?;
   let (input, _) = many0(tag(" "))(input)?;
-  let (input, args) = l1(input)?;
+  let (input, args) = math_expression(input)?;
   let (input, _) = many0(tag(" "))(input)?;
   let (input, _) = tag(")")(input)?;
   let (input, _) = many0(tag(" "))(input)?;
   Ok((input, args))
 }
+// Ebnf: list_literal = "[" , [arguments] , "]" ;
+pub fn list_literal(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("[")(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, elements) = opt(arguments)(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, _) = tag("]")(input)?;
+  let children = match elements {
+    Some(Node::FunctionArguments{ children }) => children,
+    _ => vec![],
+  };
+  Ok((input, Node::List{ children }))
+}
+// Ebnf: field_init = identifier , ":" , expression ;
+pub fn field_init(input: &str) -> IResult<&str, Node> {
+  let (input, _) = whitespace(input)?;
+  let (input, name) = name(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, _) = tag(":")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, value) = expression(input)?;
+  let (input, _) = whitespace(input)?;
+  Ok((input, Node::FieldInit{ name: name.to_string(), children: vec![value] }))
+}
+// Ebnf: struct_literal = type_name , "{" , [field_init , { "," , field_init }] , "}" ;
+// Only capitalized names start a struct literal, so `for x in xs {` still parses as a loop.
+pub fn struct_literal(input: &str) -> IResult<&str, Node> {
+  let (input, name) = type_name(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, _) = tag("{")(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, children) = separated_list0(tag(","), field_init)(input)?;
+  let (input, _) = tag("}")(input)?;
+  Ok((input, Node::StructLiteral{ name: name.to_string(), children }))
+}
+// Ebnf: type_name = uppercase , {alnum} ;
+pub fn type_name(input: &str) -> IResult<&str, &str> {
+  verify(name, |name: &str| name.starts_with(|c: char| c.is_ascii_uppercase()))(input)
+}
+// Ebnf: field_suffix = "." , (identifier | digit+) ;
+// A numeric field, as in `pair.0`, indexes into a tuple.
+pub fn field_suffix(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag(".")(input)?;
+  let (input, name) = name(input)?;
+  Ok((input, Node::FieldAccess{ name: name.to_string(), children: vec![] }))
+}
+// Ebnf: map_entry = expression , ":" , expression ;
+pub fn map_entry(input: &str) -> IResult<&str, Node> {
+  let (input, _) = whitespace(input)?;
+  let (input, key) = expression(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, _) = tag(":")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, value) = expression(input)?;
+  let (input, _) = whitespace(input)?;
+  Ok((input, Node::MapEntry{ children: vec![key, value] }))
+}
+// Ebnf: map_literal = "{" , [map_entry , { "," , map_entry }] , "}" ;
+pub fn map_literal(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("{")(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, children) = separated_list0(tag(","), map_entry)(input)?;
+  let (input, _) = tag("}")(input)?;
+  Ok((input, Node::Map{ children }))
+}
+// Ebnf: tuple_literal = "(" , expression , "," , [expression , { "," , expression }] , [","] , ")" ;
+// The comma is what tells a tuple apart from a parenthetical expression: `(1)` is a number, `(1,)` a tuple.
+pub fn tuple_literal(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("(")(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, first) = expression(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, _) = tag(",")(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, mut rest) = separated_list0(tuple((whitespace, tag(","), whitespace)), expression)(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, _) = opt(tag(","))(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, _) = tag(")")(input)?;
+  let mut children = vec![first];
+  children.append(&mut rest);
+  Ok((input, Node::Tuple{ children }))
+}
+// Ebnf: call_suffix = "(" , [arguments] , ")" ;
+pub fn call_suffix(input: &str) -> IResult<&str, Node> {
+  let start = input;
+  let (input, _) = tag("(")(input)?;
+  let (input, args) = many0(call_arguments)(input)?;
+  let (input, _) = tag(")")(input)?;
+  Ok((input, Node::Call{ children: args, span: Span::remaining(start, input) }))
+}
+// Ebnf: index_suffix = "[" , expression , "]" ;
+pub fn index_suffix(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("[")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, index) = expression(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, _) = tag("]")(input)?;
+  Ok((input, Node::Index{ children: vec![index] }))
+}
+// Ebnf: l4 = (struct_literal | function_call | list_literal | map_literal | string | float | number | boolean | identifier | tuple_literal | parenthetical_expression) , { call_suffix | index_suffix | field_suffix } ;
+// Each suffix applies to whatever the expression before it evaluated to, e.g. `adder(1)(2)`, `grid[0][1]` or `line.start.x`.
 pub fn l4(input: &str) -> IResult<&str, Node> {
-  alt((function_call, number, identifier, parenthetical_expression))(input)
+  let start = input;
+  let (input, mut head) = alt((struct_literal, function_call, list_literal, map_literal, string, float, number, terminated(boolean, not(name)), identifier, tuple_literal, parenthetical_expression))(input)?;
+  let (input, tail) = many0(alt((call_suffix, index_suffix, field_suffix)))(input)?;
+  for n in tail {
+    match n {
+      // A call's span starts at the callee, so `adder(1)(2)` points at `adder`.
+      Node::Call{mut children, span} => {
+        let mut new_children = vec![head];
+        new_children.append(&mut children);
+        head = Node::Call{children: new_children, span: Span { start: start.len(), end: span.end }};
+      }
+      Node::Index{mut children} => {
+        let mut new_children = vec![head];
+        new_children.append(&mut children);
+        head = Node::Index{children: new_children};
+      }
+      Node::FieldAccess{name, ..} => {
+        head = Node::FieldAccess{name, children: vec![head]};
+      }
+      _ => ()
+    };
+  }
+  Ok((input, head))
 }
 pub fn l3_infix(input: &str) -> IResult<&str, Node> {
   let (input, _) = many0(tag(" "))(input)?;
@@ -86,36 +484,43 @@ pub fn l3(input: &str) -> IResult<&str, Node> {
   let (input, mut head) = l4(input)?;
   let (input, tail) = many0(l3_infix)(input)?;
   for n in tail {
-    match n {
-      Node::MathExpression{name, mut children} => {
-        let mut new_children = vec![head.clone()];
-        new_children.append(&mut children);
-        head = Node::MathExpression{name, children: new_children};
-      }
-      _ => () 
-    };
+    if let Node::MathExpression{name, mut children} = n {
+      let mut new_children = vec![head.clone()];
+      new_children.append(&mut children);
+      head = Node::MathExpression{name, children: new_children};
+    }
   }
   Ok((input, head))
 }
+// Ebnf: unary = "~" , unary | l3 ;
+pub fn unary(input: &str) -> IResult<&str, Node> {
+  let (input, op) = opt(tag("~"))(input)?;
+  match op {
+    Some(op) => {
+      let (input, _) = many0(tag(" "))(input)?;
+      let (input, operand) = unary(input)?;
+      Ok((input, Node::UnaryExpression{name: op.to_string(), children: vec![operand]}))
+    }
+    None => l3(input),
+  }
+}
+// "//" has to be tried before "/" so floor division isn't read as two divisions.
 pub fn l2_infix(input: &str) -> IResult<&str, Node> {
   let (input, _) = many0(tag(" "))(input)?;
-  let (input, op) = alt((tag("*"),tag("/")))(input)?;
+  let (input, op) = alt((tag("*"),tag("//"),tag("/"),tag("%")))(input)?;
   let (input, _) = many0(tag(" "))(input)?;
-  let (input, args) = l2(input)?;
+  let (input, args) = unary(input)?;
   Ok((input, Node::MathExpression{name: op.to_string(), children: vec![args]}))
 }
 pub fn l2(input: &str) -> IResult<&str, Node> {
-  let (input, mut head) = l3(input)?;
+  let (input, mut head) = unary(input)?;
   let (input, tail) = many0(l2_infix)(input)?;
   for n in tail {
-    match n {
-      Node::MathExpression{name, mut children} => {
-        let mut new_children = vec![head.clone()];
-        new_children.append(&mut children);
-        head = Node::MathExpression{name, children: new_children};
-      }
-      _ => () 
-    };
+    if let Node::MathExpression{name, mut children} = n {
+      let mut new_children = vec![head.clone()];
+      new_children.append(&mut children);
+      head = Node::MathExpression{name, children: new_children};
+    }
   }
   Ok((input, head))
 }
@@ -130,14 +535,75 @@ pub fn l1(input: &str) -> IResult<&str, Node> {
   let (input, mut head) = l2(input)?;
   let (input, tail) = many0(l1_infix)(input)?;
   for n in tail {
-    match n {
-      Node::MathExpression{name, mut children} => {
-        let mut new_children = vec![head.clone()];
-        new_children.append(&mut children);
-        head = Node::MathExpression{name, children: new_children};
-      }
-      _ => () 
-    };
+    if let Node::MathExpression{name, mut children} = n {
+      let mut new_children = vec![head.clone()];
+      new_children.append(&mut children);
+      head = Node::MathExpression{name, children: new_children};
+    }
+  }
+  Ok((input, head))
+}
+
+pub fn shift_infix(input: &str) -> IResult<&str, Node> {
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, op) = alt((tag("<<"),tag(">>")))(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, args) = l1(input)?;
+  Ok((input, Node::MathExpression{name: op.to_string(), children: vec![args]}))
+}
+// Ebnf: shift = l1 , { ("<<" | ">>") , l1 } ;
+pub fn shift(input: &str) -> IResult<&str, Node> {
+  let (input, mut head) = l1(input)?;
+  let (input, tail) = many0(shift_infix)(input)?;
+  for n in tail {
+    if let Node::MathExpression{name, mut children} = n {
+      let mut new_children = vec![head.clone()];
+      new_children.append(&mut children);
+      head = Node::MathExpression{name, children: new_children};
+    }
+  }
+  Ok((input, head))
+}
+// A single "&" or "|", so "&&" and "||" are left free for boolean operators.
+pub fn bit_and_infix(input: &str) -> IResult<&str, Node> {
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, op) = tag("&")(input)?;
+  let (input, _) = not(tag("&"))(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, args) = shift(input)?;
+  Ok((input, Node::MathExpression{name: op.to_string(), children: vec![args]}))
+}
+// Ebnf: bit_and = shift , { "&" , shift } ;
+pub fn bit_and(input: &str) -> IResult<&str, Node> {
+  let (input, mut head) = shift(input)?;
+  let (input, tail) = many0(bit_and_infix)(input)?;
+  for n in tail {
+    if let Node::MathExpression{name, mut children} = n {
+      let mut new_children = vec![head.clone()];
+      new_children.append(&mut children);
+      head = Node::MathExpression{name, children: new_children};
+    }
+  }
+  Ok((input, head))
+}
+pub fn bit_or_infix(input: &str) -> IResult<&str, Node> {
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, op) = tag("|")(input)?;
+  let (input, _) = not(tag("|"))(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, args) = bit_and(input)?;
+  Ok((input, Node::MathExpression{name: op.to_string(), children: vec![args]}))
+}
+// Ebnf: bit_or = bit_and , { "|" , bit_and } ;
+pub fn bit_or(input: &str) -> IResult<&str, Node> {
+  let (input, mut head) = bit_and(input)?;
+  let (input, tail) = many0(bit_or_infix)(input)?;
+  for n in tail {
+    if let Node::MathExpression{name, mut children} = n {
+      let mut new_children = vec![head.clone()];
+      new_children.append(&mut children);
+      head = Node::MathExpression{name, children: new_children};
+    }
   }
   Ok((input, head))
 }
@@ -145,7 +611,7 @@ pub fn l1(input: &str) -> IResult<&str, Node> {
 // l0 - to account for comparison operators 
 
 pub fn math_expression(input: &str) -> IResult<&str, Node> {
-  l1(input)
+  bit_or(input)
 }
 
 // Cut 1
@@ -153,7 +619,7 @@ pub fn math_expression(input: &str) -> IResult<&str, Node> {
 pub fn comparison_expression(input: &str) -> IResult<&str, Node> {
   let (input, left) = alt((math_expression, function_call, number, boolean, identifier))(input)?; // match left side
   let (input, _) = many0(tag(" "))(input)?;
-  let (input, operator) = alt((tag(">"), tag("<"), tag(">="), tag("<="), tag("=="), tag("!=")))(input)?; // choose a comparison operator
+  let (input, operator) = alt((tag(">="), tag("<="), tag(">"), tag("<"), tag("=="), tag("!=")))(input)?; // choose a comparison operator, trying the two-character ones first
   let (input, _) = many0(tag(" "))(input)?;
   let (input, right) = alt((math_expression, function_call, number, boolean, identifier))(input)?; // match right side
   Ok((input, Node::ComparisonExpression{ name: operator.to_string(), children: vec![left, right]}))   
@@ -232,33 +698,203 @@ pub fn if_expression(input: &str) -> IResult<&str, Node> {
   Ok((input, Node::IfExpression { children }))
 }
 
+// Ebnf: wildcard = "_" ;
+pub fn wildcard(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("_")(input)?;
+  let (input, _) = not(name)(input)?;
+  Ok((input, Node::Wildcard))
+}
+
+// Ebnf: variant_pattern = type_name , ["(" , [pattern , { "," , pattern }] , ")"] ;
+pub fn variant_pattern(input: &str) -> IResult<&str, Node> {
+  let (input, name) = type_name(input)?;
+  let (input, fields) = opt(tuple((
+    tag("("),
+    whitespace,
+    separated_list0(tuple((tag(","), whitespace)), pattern),
+    whitespace,
+    tag(")"),
+  )))(input)?;
+  let children = match fields {
+    Some((_, _, children, _, _)) => children,
+    None => vec![],
+  };
+  Ok((input, Node::VariantPattern{ name: name.to_string(), children }))
+}
+
+// Ebnf: list_pattern = "[" , [pattern , { "," , pattern }] , "]" ;
+pub fn list_pattern(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("[")(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, children) = separated_list0(tuple((tag(","), whitespace)), pattern)(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, _) = tag("]")(input)?;
+  Ok((input, Node::ListPattern{ children }))
+}
+
+// Ebnf: tuple_pattern = "(" , pattern , "," , [pattern , { "," , pattern }] , [","] , ")" ;
+pub fn tuple_pattern(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("(")(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, first) = pattern(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, _) = tag(",")(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, mut rest) = separated_list0(tuple((tag(","), whitespace)), pattern)(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, _) = opt(tag(","))(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, _) = tag(")")(input)?;
+  let mut children = vec![first];
+  children.append(&mut rest);
+  Ok((input, Node::TuplePattern{ children }))
+}
+
+// Ebnf: field_pattern = identifier , [":" , pattern] ;
+// A field without a pattern binds the field to a variable of the same name.
+pub fn field_pattern(input: &str) -> IResult<&str, Node> {
+  let (input, _) = whitespace(input)?;
+  let (input, name) = name(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, sub_pattern) = opt(preceded(tuple((tag(":"), many0(tag(" ")))), pattern))(input)?;
+  let (input, _) = whitespace(input)?;
+  let sub_pattern = sub_pattern.unwrap_or(Node::Identifier{ value: name.to_string() });
+  Ok((input, Node::FieldInit{ name: name.to_string(), children: vec![sub_pattern] }))
+}
+
+// Ebnf: struct_pattern = type_name , "{" , [field_pattern , { "," , field_pattern }] , "}" ;
+pub fn struct_pattern(input: &str) -> IResult<&str, Node> {
+  let (input, name) = type_name(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, _) = tag("{")(input)?;
+  let (input, children) = separated_list0(tag(","), field_pattern)(input)?;
+  let (input, _) = tag("}")(input)?;
+  Ok((input, Node::StructPattern{ name: name.to_string(), children }))
+}
+
+// Ebnf: pattern = wildcard | list_pattern | tuple_pattern | struct_pattern | variant_pattern | boolean | number | string | identifier ;
+// A lowercase identifier binds the matched value to that name. String patterns must be plain literals, not
+// interpolations.
+pub fn pattern(input: &str) -> IResult<&str, Node> {
+  let string = verify(string, |node: &Node| matches!(node, Node::String { .. }));
+  alt((wildcard, list_pattern, tuple_pattern, struct_pattern, variant_pattern, boolean, number, string, identifier))(input)
+}
+
+// Ebnf: match_arm = pattern , "=>" , expression ;
+pub fn match_arm(input: &str) -> IResult<&str, Node> {
+  let (input, _) = whitespace(input)?;
+  let (input, pattern) = pattern(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, _) = tag("=>")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, body) = expression(input)?;
+  let (input, _) = whitespace(input)?;
+  Ok((input, Node::MatchArm{ children: vec![pattern, body] }))
+}
+
+// Ebnf: match_expression = "match" , expression , "{" , match_arm , { "," , match_arm } , [","] , "}" ;
+pub fn match_expression(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("match ")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, scrutinee) = expression(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, _) = tag("{")(input)?;
+  let (input, mut arms) = separated_list1(tag(","), match_arm)(input)?;
+  let (input, _) = opt(tag(","))(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, _) = tag("}")(input)?;
+  let mut children = vec![scrutinee];
+  children.append(&mut arms);
+  Ok((input, Node::MatchExpression{ children }))
+}
+
 pub fn expression(input: &str) -> IResult<&str, Node> {
-  let (input, result) = alt((if_expression, boolean, comparison_expression, math_expression, function_call, number, string, identifier))(input)?;
+  let (input, result) = alt((if_expression, match_expression, function_literal, closure_literal, boolean, comparison_expression, math_expression, function_call, number, string, identifier))(input)?;
   Ok((input, Node::Expression{ children: vec![result]}))   
 }
 
 pub fn statement(input: &str) -> IResult<&str, Node> {
   let (input, _) = many0(alt((tag(" "),tag("\t"))))(input)?;
-  let (input, result) = alt((variable_define, function_return))(input)?;
+  let start = input;
+  let (input, result) = alt((variable_define, function_return, throw, for_loop, try_catch, assignment, expression))(input)?;
+  let span = Span::remaining(start, input);
   let (input, _) = many0(tag(";"))(input)?;
   let (input, _) = many0(tag(" "))(input)?;
   let (input, _) = many0(tag("\n"))(input)?;
-  Ok((input, Node::Statement{ children: vec![result]}))   
+  Ok((input, Node::Statement{ children: vec![result], span }))
 }
 pub fn function_return(input: &str) -> IResult<&str, Node> {
   let (input, _) = tag("return ")(input)?;
-  let (input, return_value) = alt((function_call, expression, identifier))(input)?;
+  let (input, return_value) = alt((expression, function_call, identifier))(input)?;
   Ok((input, Node::FunctionReturn{ children: vec![return_value]}))
 }
+// Ebnf: variable_define = "let" , (list_pattern | tuple_pattern | struct_pattern | wildcard | identifier) , "=" , expression ;
 pub fn variable_define(input: &str) -> IResult<&str, Node> {
   let (input, _) = tag("let ")(input)?;
-  let (input, variable) = identifier(input)?;
+  let (input, variable) = alt((list_pattern, tuple_pattern, struct_pattern, wildcard, identifier))(input)?;
   let (input, _) = many0(tag(" "))(input)?;
   let (input, _) = tag("=")(input)?;
   let (input, _) = many0(tag(" "))(input)?;
   let (input, expression) = expression(input)?;
   Ok((input, Node::VariableDefine{ children: vec![variable, expression]}))   
 }
+// Ebnf: for_loop = "for" , identifier , "in" , expression , "{" , statement+ , "}" ;
+pub fn for_loop(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("for ")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, variable) = identifier(input)?;
+  let (input, _) = many1(tag(" "))(input)?;
+  let (input, _) = tag("in ")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, iterable) = expression(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, _) = tag("{")(input)?;
+  let (input, mut statements) = many1(preceded(whitespace, statement))(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, _) = tag("}")(input)?;
+  let mut children = vec![variable, iterable];
+  children.append(&mut statements);
+  Ok((input, Node::ForLoop{ children }))
+}
+// Ebnf: block = "{" , { statement } , "}" ;
+pub fn block(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("{")(input)?;
+  let (input, statements) = many0(preceded(whitespace, statement))(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, _) = tag("}")(input)?;
+  Ok((input, Node::Block{ children: statements }))
+}
+// Ebnf: try_catch = "try" , block , "catch" , "(" , identifier , ")" , block ;
+pub fn try_catch(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("try")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, body) = block(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, _) = tag("catch")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, _) = tag("(")(input)?;
+  let (input, variable) = identifier(input)?;
+  let (input, _) = tag(")")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, handler) = block(input)?;
+  Ok((input, Node::TryCatch{ children: vec![body, variable, handler] }))
+}
+// Ebnf: throw = "throw" , expression ;
+pub fn throw(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("throw ")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, value) = expression(input)?;
+  Ok((input, Node::Throw{ children: vec![value] }))
+}
+// Ebnf: assignment = (identifier | l4 , (index_suffix | field_suffix)) , "=" , expression ;
+pub fn assignment(input: &str) -> IResult<&str, Node> {
+  let (input, target) = l4(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, _) = tag("=")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, value) = expression(input)?;
+  Ok((input, Node::Assignment{ children: vec![target, value]}))
+}
 pub fn arguments(input: &str) -> IResult<&str, Node> {
   let (input, arg) = expression(input)?;
   let (input, mut others) = many0(other_arg)(input)?;
@@ -268,13 +904,54 @@ pub fn arguments(input: &str) -> IResult<&str, Node> {
 }
 pub fn other_arg(input: &str) -> IResult<&str, Node> {
   let (input, _) = tag(",")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
   expression(input)
 }
+// Ebnf: call_arguments = (spread | named_argument | expression) , { "," , (spread | named_argument | expression) } ;
+pub fn call_arguments(input: &str) -> IResult<&str, Node> {
+  let (input, args) = separated_list1(tuple((tag(","), many0(tag(" ")))), alt((spread, named_argument, expression)))(input)?;
+  Ok((input, Node::FunctionArguments{children: args}))
+}
+// Ebnf: spread = "..." , expression ;
+pub fn spread(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("...")(input)?;
+  let (input, value) = expression(input)?;
+  Ok((input, Node::Spread{children: vec![value]}))
+}
+// Ebnf: named_argument = name , ":" , expression ;
+pub fn named_argument(input: &str) -> IResult<&str, Node> {
+  let (input, name) = name(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, _) = tag(":")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, value) = expression(input)?;
+  Ok((input, Node::NamedArgument{name: name.to_string(), children: vec![value]}))
+}
+// Ebnf: parameters = (parameter , { "," , parameter } , ["," , rest_parameter]) | rest_parameter ;
+// Parameters without a default must also come before those with one, which the interpreter checks so it can
+// name the parameter that doesn't.
+pub fn parameters(input: &str) -> IResult<&str, Node> {
+  let rest_is_last = |params: &Vec<Node>| !params[..params.len() - 1].iter().any(|param| matches!(param, Node::RestParameter{..}));
+  let (input, params) = verify(separated_list1(tuple((tag(","), many0(tag(" ")))), alt((rest_parameter, parameter))), rest_is_last)(input)?;
+  Ok((input, Node::FunctionArguments{children: params}))
+}
+// Ebnf: rest_parameter = "..." , name ;
+pub fn rest_parameter(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("...")(input)?;
+  let (input, name) = name(input)?;
+  Ok((input, Node::RestParameter{name: name.to_string()}))
+}
+// Ebnf: parameter = name , ["=" , expression] ;
+pub fn parameter(input: &str) -> IResult<&str, Node> {
+  let (input, name) = name(input)?;
+  let (input, default) = opt(preceded(tuple((many0(tag(" ")), tag("="), many0(tag(" ")))), expression))(input)?;
+  Ok((input, Node::Parameter{name: name.to_string(), children: default.into_iter().collect()}))
+}
 pub fn function_definition(input: &str) -> IResult<&str, Node> {
   let (input, _) = tag("fn ")(input)?;
   let (input, function_name) = identifier(input)?;
   let (input, _) = tag("(")(input)?;
-  let (input, mut args) = many0(arguments)(input)?;
+  let (input, mut args) = many0(parameters)(input)?;
   let (input, _) = tag(")")(input)?;
   let (input, _) = many0(tag(" "))(input)?;
   let (input, _) = tag("{")(input)?;
@@ -283,13 +960,123 @@ pub fn function_definition(input: &str) -> IResult<&str, Node> {
   let (input, _) = tag("}")(input)?;
   let (input, _) = many0(alt((tag("\n"),tag(" "))))(input)?;
   let mut children = vec![function_name];
-  println!("args, {:?}", args);
   children.append(&mut args);
   children.append(&mut statements);
-  Ok((input, Node::FunctionDefine{ children: children }))   
+  Ok((input, Node::FunctionDefine{ children }))   
+}
+
+// Consume any run of spaces, tabs and newlines between top-level items.
+pub fn whitespace(input: &str) -> IResult<&str, Vec<&str>> {
+  many0(alt((tag(" "),tag("\t"),tag("\r"),tag("\n"))))(input)
+}
+
+// Ebnf: function_literal = "fn" , "(" , [parameters] , ")" , "{" , statement+ , "}" ;
+pub fn function_literal(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("fn")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, _) = tag("(")(input)?;
+  let (input, mut args) = many0(parameters)(input)?;
+  let (input, _) = tag(")")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, _) = tag("{")(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, mut statements) = many1(statement)(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, _) = tag("}")(input)?;
+  let mut children = vec![];
+  children.append(&mut args);
+  children.append(&mut statements);
+  Ok((input, Node::FunctionLiteral{ children }))
+}
+
+// Ebnf: closure_literal = "|" , [name , { "," , name }] , "|" , expression ;
+// The body is a single expression, so it is stored as the closure's return statement. Parameters are plain
+// identifiers rather than expressions, otherwise the closing "|" would be read as a bitwise or.
+pub fn closure_literal(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("|")(input)?;
+  let (input, params) = separated_list0(tuple((tag(","), many0(tag(" ")))), name)(input)?;
+  let (input, _) = tag("|")(input)?;
+  let mut args = vec![];
+  if !params.is_empty() {
+    let params = params.into_iter().map(|param| Node::Parameter{ name: param.to_string(), children: vec![] }).collect();
+    args.push(Node::FunctionArguments{ children: params });
+  }
+  let (input, _) = many0(tag(" "))(input)?;
+  let start = input;
+  let (input, body) = expression(input)?;
+  let mut children = vec![];
+  children.append(&mut args);
+  children.push(Node::Statement{ children: vec![Node::FunctionReturn{ children: vec![body] }], span: Span::remaining(start, input) });
+  Ok((input, Node::FunctionLiteral{ children }))
+}
+
+// Ebnf: struct_definition = "struct" , type_name , "{" , [identifier , { "," , identifier }] , "}" ;
+pub fn struct_definition(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("struct ")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, name) = type_name(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, _) = tag("{")(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, fields) = separated_list0(tuple((tag(","), whitespace)), identifier)(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, _) = opt(tag(","))(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, _) = tag("}")(input)?;
+  Ok((input, Node::StructDefine{ name: name.to_string(), children: fields }))
+}
+
+// Ebnf: enum_variant = type_name , ["(" , [identifier , { "," , identifier }] , ")"] ;
+pub fn enum_variant(input: &str) -> IResult<&str, Node> {
+  let (input, _) = whitespace(input)?;
+  let (input, name) = type_name(input)?;
+  let (input, fields) = opt(tuple((
+    tag("("),
+    whitespace,
+    separated_list0(tuple((tag(","), whitespace)), identifier),
+    whitespace,
+    tag(")"),
+  )))(input)?;
+  let (input, _) = whitespace(input)?;
+  let children = match fields {
+    Some((_, _, children, _, _)) => children,
+    None => vec![],
+  };
+  Ok((input, Node::EnumVariant{ name: name.to_string(), children }))
 }
 
+// Ebnf: enum_definition = "enum" , type_name , "{" , enum_variant , { "," , enum_variant } , [","] , "}" ;
+pub fn enum_definition(input: &str) -> IResult<&str, Node> {
+  let (input, _) = tag("enum ")(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, name) = type_name(input)?;
+  let (input, _) = many0(tag(" "))(input)?;
+  let (input, _) = tag("{")(input)?;
+  let (input, variants) = separated_list1(tag(","), enum_variant)(input)?;
+  let (input, _) = opt(tag(","))(input)?;
+  let (input, _) = whitespace(input)?;
+  let (input, _) = tag("}")(input)?;
+  Ok((input, Node::EnumDefine{ name: name.to_string(), children: variants }))
+}
+
+// Ebnf: program = { function_definition | struct_definition | enum_definition | statement } ;
+// Top-level statements are kept in source order so the runtime can execute them as a script.
 pub fn program(input: &str) -> IResult<&str, Node> {
-  let (input, result) = many1(alt((function_definition, statement, expression)))(input)?;  // Now that we've defined a number and an identifier, we can compose them using more combinators. Here we use the "alt" combinator to propose a choice.
+  let source_len = input.len();
+  let (input, mut result) = many1(preceded(whitespace, alt((function_definition, struct_definition, enum_definition, statement))))(input)?;  // Now that we've defined a number and an identifier, we can compose them using more combinators. Here we use the "alt" combinator to propose a choice.
+  let (input, _) = whitespace(input)?;
+  for node in result.iter_mut() {
+    locate_spans(node, source_len);
+  }
   Ok((input, Node::Program{ children: result}))       // Whether the result is an identifier or a number, we attach that to the program
 }
+
+// Turn the remaining-input lengths recorded while parsing into byte offsets from the start of the source.
+fn locate_spans(node: &mut Node, source_len: usize) {
+  if let Node::Statement { span, .. } | Node::FunctionCall { span, .. } | Node::Call { span, .. } = node {
+    *span = Span { start: source_len - span.start, end: source_len - span.end };
+  }
+  for child in node.children_mut() {
+    locate_spans(child, source_len);
+  }
+}
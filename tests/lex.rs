@@ -1,4 +1,4 @@
-use lexer::*;
+use asalang::*;
 
 #[test]
 fn test_01() {
@@ -198,3 +198,37 @@ fn test_10() {
     Token::EOF,
   ]);
 }
+
+#[test]
+fn test_11() {
+  assert_eq!(strip_whitespace(&lex("7 % 2 // 3 / 5")),vec![
+    Token::Digit(b'7'),
+    Token::Percent(b'%'),
+    Token::Digit(b'2'),
+    Token::Slash(b'/'),
+    Token::Slash(b'/'),
+    Token::Digit(b'3'),
+    Token::Slash(b'/'),
+    Token::Digit(b'5'),
+    Token::EOF,
+  ]);
+}
+
+#[test]
+fn test_12() {
+  assert_eq!(strip_whitespace(&lex("~a & b | c << 1 >> 2")),vec![
+    Token::Tilde(b'~'),
+    Token::Alpha(b'a'),
+    Token::Ampersand(b'&'),
+    Token::Alpha(b'b'),
+    Token::Pipe(b'|'),
+    Token::Alpha(b'c'),
+    Token::LeftAngle(b'<'),
+    Token::LeftAngle(b'<'),
+    Token::Digit(b'1'),
+    Token::RightAngle(b'>'),
+    Token::RightAngle(b'>'),
+    Token::Digit(b'2'),
+    Token::EOF,
+  ]);
+}
@@ -1,8 +1,7 @@
 extern crate asalang;
 extern crate nom;
 
-use asalang::{program, Node, Value, start_interpreter};
-use nom::IResult;
+use asalang::{program, Value, RuntimeError, Runtime, DEFAULT_MAX_CALL_DEPTH, DEFAULT_MAX_STACK_SIZE, start_interpreter};
 
 macro_rules! test {
   ($func:ident, $test:tt, $expected:expr) => (
@@ -11,7 +10,7 @@ macro_rules! test {
       match program($test) {
         Ok((input, p)) => {
           assert_eq!(input, "");
-          assert_eq!(start_interpreter(&p), $expected);
+          assert_eq!(start_interpreter(&p).map_err(|error| error.error), $expected);
           Ok(())
         },
         Err(e) => Err(format!("{:?}",e)),
@@ -21,18 +20,18 @@ macro_rules! test {
 }
 
 test!(numeric, r#"123"#, Ok(Value::Number(123)));
-test!(identifier, r#"x"#, Err("Undefined variable"));
+test!(identifier, r#"x"#, Err(RuntimeError::UndefinedVariable { name: "x".to_string() }));
 test!(string, r#""hello world""#, Ok(Value::String("hello world".to_string())));
 test!(bool_true, r#"true"#, Ok(Value::Bool(true)));
 test!(bool_false, r#"false"#, Ok(Value::Bool(false)));
-test!(function_call, r#"foo()"#, Err("Undefined function"));
-test!(function_call_one_arg, r#"foo(a)"#, Err("Undefined function"));
-test!(function_call_more_args, r#"foo(a,b,c)"#, Err("Undefined function"));
-test!(variable_define, r#"let x = 123;"#, Ok(Value::Number(123)));
-test!(variable_init, r#"let x = 1;"#, Ok(Value::Number(1)));
-test!(variable_bool, r#"let bool = true;"#, Ok(Value::Bool(true)));
-test!(variable_string, r#"let string = "Hello World";"#, Ok(Value::String("Hello World".to_string())));
-test!(variable_init_no_space, r#"let x=1;"#, Ok(Value::Number(1)));
+test!(function_call, r#"foo()"#, Err(RuntimeError::UndefinedFunction { name: "foo".to_string() }));
+test!(function_call_one_arg, r#"foo(a)"#, Err(RuntimeError::UndefinedFunction { name: "foo".to_string() }));
+test!(function_call_more_args, r#"foo(a,b,c)"#, Err(RuntimeError::UndefinedFunction { name: "foo".to_string() }));
+test!(variable_define, r#"let x = 123; x"#, Ok(Value::Number(123)));
+test!(variable_init, r#"let x = 1; x"#, Ok(Value::Number(1)));
+test!(variable_bool, r#"let bool = true; bool"#, Ok(Value::Bool(true)));
+test!(variable_string, r#"let string = "Hello World"; string"#, Ok(Value::String("Hello World".to_string())));
+test!(variable_init_no_space, r#"let x=1; x"#, Ok(Value::Number(1)));
 test!(math, r#"1 + 1"#, Ok(Value::Number(2)));
 test!(math_no_space, r#"1+1"#, Ok(Value::Number(2)));
 test!(math_subtraction, r#"1 - 1"#, Ok(Value::Number(0)));
@@ -41,9 +40,9 @@ test!(math_divide, r#"6 / 2"#, Ok(Value::Number(3)));
 test!(math_exponent, r#"2 ^ 4"#, Ok(Value::Number(16)));
 test!(math_more_terms, r#"10 + 2*6"#, Ok(Value::Number(22)));
 test!(math_more_terms_paren, r#"((10+2)*6)/4"#, Ok(Value::Number(18)));
-test!(assign_math, r#"let x = 1 + 1;"#, Ok(Value::Number(2)));
-test!(assign_function, r#"let x = foo();"#, Err("Undefined function"));
-test!(assign_function_arguments, r#"let x = foo(a,b,c);"#, Err("Undefined function"));
+test!(assign_math, r#"let x = 1 + 1; x"#, Ok(Value::Number(2)));
+test!(assign_function, r#"let x = foo();"#, Err(RuntimeError::UndefinedFunction { name: "foo".to_string() }));
+test!(assign_function_arguments, r#"let x = foo(a,b,c);"#, Err(RuntimeError::UndefinedFunction { name: "foo".to_string() }));
 test!(define_function, r#"fn main(){return foo();} fn foo(){return 5;}"#, Ok(Value::Number(5)));
 test!(define_function_args, r#"fn main(){return foo(1,2,3);} fn foo(a,b,c){return a+b+c;}"#, Ok(Value::Number(6)));
 test!(define_function_more_statement, r#"fn main() {
@@ -70,11 +69,11 @@ fn main() {
 // Cut 1
 test!(greater_than, r#"1 > 2"#, Ok(Value::Bool(false)));
 test!(math_expression_equality, r#"(1 + 2) == 3"#, Ok(Value::Bool(true)));
-test!(assign_comparison, r#"let result = 1 < 2;"#, Ok(Value::Bool(true)));
+test!(assign_comparison, r#"let result = 1 < 2; result"#, Ok(Value::Bool(true)));
 
 // Invalid test 
-test!(invalid, r#"1 > false"#, Err("Invalid comparison expression"));
-test!(invalid_math_bool, r#"5 - false"#, Err("Invalid"));
+test!(invalid, r#"1 > false"#, Err(RuntimeError::TypeMismatch { op: ">".to_string(), lhs: "int", rhs: "bool" }));
+test!(invalid_math_bool, r#"5 - false"#, Err(RuntimeError::TypeMismatch { op: "-".to_string(), lhs: "int", rhs: "bool" }));
 
 // Cut 2
 // Valid
@@ -86,10 +85,585 @@ test!(multi_line, r#"if true {
   return true;
 }
 "#, Ok(Value::Bool(false)));
-test!(assign_if_else, r#"let x = if true {return false;} else {return true;}"#, Ok(Value::Bool(false)));
+test!(assign_if_else, r#"let x = if true {return false;} else {return true;} x"#, Ok(Value::Bool(false)));
 test!(else_if, r#"if true {return 1;} else if false {return 2;} else {return 3;}"#, Ok(Value::Number(1)));
 
 // Invalid - not working for some reason
 // Returns an error when I use cargo run in main.rs. It returns the error of undefined, but the test is not matching with 
 // the error for some reason. 
 // test!(missing_curly, r#"if true {return false;} return true;}"#, Err("Undefined variable"));
+
+// Script mode
+test!(script_statements_in_order, r#"let x = 1; x + 1"#, Ok(Value::Number(2)));
+test!(script_multi_line, r#"let x = 2;
+let y = x * 3;
+y + 1
+"#, Ok(Value::Number(7)));
+test!(script_global_in_function, r#"let scale = 10;
+fn grow(a) {
+  return a * scale;
+}
+grow(4)"#, Ok(Value::Number(40)));
+test!(script_call_before_define, r#"let x = foo(); fn foo(){return 5;} x"#, Ok(Value::Number(5)));
+test!(script_with_main, r#"let base = 3; fn main(){return base + 1;}"#, Ok(Value::Number(4)));
+
+// Closures
+test!(closure_literal, r#"let inc = |x| x + 1; inc(41)"#, Ok(Value::Number(42)));
+test!(function_literal, r#"let add = fn(a, b) { return a + b; }; add(2, 3)"#, Ok(Value::Number(5)));
+test!(closure_captures_environment, r#"fn adder(n) {
+  return |x| x + n;
+}
+let add2 = adder(2);
+add2(5)"#, Ok(Value::Number(7)));
+test!(call_returned_function, r#"fn adder(n) { return fn(x) { return x + n; }; } adder(1)(2)"#, Ok(Value::Number(3)));
+test!(closure_calls_itself, r#"fn f() { let fact = |n| match n { 0 => 1, _ => n * fact(n - 1) }; return fact(5); } f()"#, Ok(Value::Number(120)));
+test!(closure_sees_later_assignment, r#"fn f() { let x = 1; let g = || x; x = 2; return g(); } f()"#, Ok(Value::Number(2)));
+test!(closure_assigns_captured_variable, r#"fn f() { let n = 0; let inc = fn() { n = n + 1; return n; }; inc(); inc(); return n; } f()"#, Ok(Value::Number(2)));
+test!(closure_keeps_captured_variable, r#"fn counter() { let n = 0; return fn() { n = n + 1; return n; }; } let c = counter(); c(); c(); c()"#, Ok(Value::Number(3)));
+test!(closure_nested_capture, r#"fn f(a) { let g = |b| |c| a + b + c; return g(2)(3); } f(1)"#, Ok(Value::Number(6)));
+test!(higher_order_function, r#"fn twice(f, x) { return f(f(x)); }
+fn double(x) { return x * 2; }
+twice(double, 3)"#, Ok(Value::Number(12)));
+test!(call_non_function, r#"let x = 1; x(2)"#, Err(RuntimeError::NotAFunction { found: "int" }));
+
+// Lists
+test!(list_literal, r#"[1, 2, 3]"#, Ok(Value::list(vec![Value::Number(1), Value::Number(2), Value::Number(3)])));
+test!(list_empty, r#"len([])"#, Ok(Value::Number(0)));
+test!(list_index, r#"let xs = [10, 20, 30]; xs[1] + xs[2]"#, Ok(Value::Number(50)));
+test!(list_index_out_of_bounds, r#"let xs = [1]; xs[3]"#, Err(RuntimeError::IndexOutOfBounds { index: 3, len: 1 }));
+test!(list_index_bignum, r#"let xs = [1]; xs[2 ^ 70]"#, Err(RuntimeError::IndexOutOfRange { index: "1180591620717411303424".to_string(), len: 1 }));
+test!(list_index_float, r#"let xs = [1]; xs[0.0]"#, Err(RuntimeError::InvalidIndex { found: "float" }));
+test!(list_index_string, r#"let xs = [1]; xs["0"]"#, Err(RuntimeError::InvalidIndex { found: "string" }));
+test!(list_assign_index_float, r#"let xs = [1]; xs[0.0] = 2;"#, Err(RuntimeError::InvalidIndex { found: "float" }));
+test!(list_index_assign, r#"let xs = [1, 2]; xs[0] = 5; xs"#, Ok(Value::list(vec![Value::Number(5), Value::Number(2)])));
+test!(list_nested_index, r#"let grid = [[1, 2], [3, 4]]; grid[1][0]"#, Ok(Value::Number(3)));
+test!(list_push_pop, r#"let xs = [1]; push(xs, 2); push(xs, 3); pop(xs) + len(xs)"#, Ok(Value::Number(5)));
+test!(list_pop_empty, r#"pop([])"#, Err(RuntimeError::PopFromEmptyList));
+test!(list_slice, r#"slice([1, 2, 3, 4], 1, 3)"#, Ok(Value::list(vec![Value::Number(2), Value::Number(3)])));
+test!(list_slice_out_of_bounds, r#"slice([1, 2], 1, 3)"#, Err(RuntimeError::IndexOutOfBounds { index: 3, len: 2 }));
+test!(list_equality, r#"[1, 2] == [1, 2]"#, Ok(Value::Bool(true)));
+test!(list_shared_in_function, r#"fn add(xs, x) { push(xs, x); return len(xs); } let xs = []; add(xs, 1); add(xs, 2)"#, Ok(Value::Number(2)));
+test!(variable_assign, r#"let x = 1; x = x + 1; x"#, Ok(Value::Number(2)));
+
+#[test]
+fn list_display() {
+  let list = Value::list(vec![Value::Number(1), Value::String("a".to_string()), Value::list(vec![])]);
+  assert_eq!(list.to_string(), r#"[1, "a", []]"#);
+}
+
+// Maps
+test!(map_lookup, r#"let m = {"a": 1, "b": 2}; m["a"] + m["b"]"#, Ok(Value::Number(3)));
+test!(map_missing_key, r#"let m = {"a": 1}; m["z"]"#, Err(RuntimeError::KeyNotFound { key: "z".to_string() }));
+test!(map_insert, r#"let m = {}; m["x"] = 5; m["x"] = 6; len(m) + m["x"]"#, Ok(Value::Number(7)));
+test!(map_remove, r#"let m = {"a": 1, "b": 2}; remove(m, "a"); contains(m, "a")"#, Ok(Value::Bool(false)));
+test!(map_keys_in_insertion_order, r#"let m = {"b": 1, "a": 2}; m["c"] = 3; keys(m)"#, Ok(Value::list(vec![
+  Value::String("b".to_string()), Value::String("a".to_string()), Value::String("c".to_string())])));
+test!(map_values, r#"values({"a": 1, "b": 2})"#, Ok(Value::list(vec![Value::Number(1), Value::Number(2)])));
+test!(map_equality_ignores_order, r#"{"a": 1, "b": 2} == {"b": 2, "a": 1}"#, Ok(Value::Bool(true)));
+test!(map_invalid_key, r#"{[1]: 2}"#, Err(RuntimeError::InvalidMapKey { found: "list" }));
+test!(for_over_list, r#"let total = 0;
+for x in [1, 2, 3] {
+  total = total + x;
+}
+total"#, Ok(Value::Number(6)));
+test!(for_over_map, r#"let m = {"a": 1, "b": 2};
+let total = 0;
+for k in m { total = total + m[k]; }
+total"#, Ok(Value::Number(3)));
+test!(for_return_exits_function, r#"fn first(xs) {
+  for x in xs {
+    return x;
+  }
+  return 0;
+}
+first([7, 8])"#, Ok(Value::Number(7)));
+test!(return_stops_function, r#"fn foo() { return 1; return 2; } foo()"#, Ok(Value::Number(1)));
+test!(return_stops_script, r#"let x = 1; return x; x = 2; x"#, Ok(Value::Number(1)));
+test!(return_in_script_then_main, r#"return 1; fn main() { let a = 1; return 2; }"#, Ok(Value::Number(2)));
+
+// Structs
+test!(struct_field_access, r#"struct Point { x, y }
+let p = Point { x: 1, y: 2 };
+p.x + p.y"#, Ok(Value::Number(3)));
+test!(struct_field_update, r#"struct Point { x, y } let p = Point { x: 1, y: 2 }; p.x = 3; p.x"#, Ok(Value::Number(3)));
+test!(struct_nested_access, r#"struct Point { x, y }
+struct Line { start, end }
+let l = Line { start: Point { x: 1, y: 2 }, end: Point { x: 3, y: 4 } };
+l.end.y"#, Ok(Value::Number(4)));
+test!(struct_equality, r#"struct Point { x, y } Point { x: 1, y: 2 } == Point { y: 2, x: 1 }"#, Ok(Value::Bool(true)));
+test!(struct_unknown_field_access, r#"struct Point { x, y } let p = Point { x: 1, y: 2 }; p.z"#, Err(RuntimeError::UnknownField { name: "z".to_string() }));
+test!(struct_unknown_field_static, r#"struct Point { x, y }
+fn never() { return Point { x: 1, z: 2 }; }
+1"#, Err(RuntimeError::UnknownField { name: "z".to_string() }));
+test!(struct_missing_field, r#"struct Point { x, y } Point { x: 1 }"#, Err(RuntimeError::MissingField { name: "y".to_string() }));
+test!(struct_undefined, r#"Point { x: 1 }"#, Err(RuntimeError::UndefinedStruct { name: "Point".to_string() }));
+
+#[test]
+fn struct_display() -> Result<(), String> {
+  let (_, p) = program(r#"struct Point { x, y } Point { x: 1, y: "a" }"#).map_err(|e| format!("{:?}", e))?;
+  assert_eq!(start_interpreter(&p).map(|v| v.to_string()), Ok(r#"Point { x: 1, y: "a" }"#.to_string()));
+  Ok(())
+}
+
+// Enums and match
+test!(match_variant, r#"enum Shape { Circle(r), Rect(w, h) }
+fn area(s) {
+  return match s {
+    Circle(r) => 3 * r * r,
+    Rect(w, h) => w * h,
+  };
+}
+area(Circle(2)) + area(Rect(2, 5))"#, Ok(Value::Number(22)));
+test!(match_unit_variant, r#"enum Color { Red, Green } match Green { Red => 1, Green => 2 }"#, Ok(Value::Number(2)));
+test!(match_literal, r#"match 3 { 1 => 10, 3 => 30, _ => 0 }"#, Ok(Value::Number(30)));
+test!(match_string_literal, r#"match "b" { "a" => 1, "b" => 2, _ => 0 }"#, Ok(Value::Number(2)));
+test!(match_wildcard, r#"match 5 { 1 => 10, _ => 0 }"#, Ok(Value::Number(0)));
+test!(match_binding, r#"match 5 { 1 => 10, n => n * 2 }"#, Ok(Value::Number(10)));
+test!(match_nested_pattern, r#"enum Shape { Circle(r), Rect(w, h) } match Rect(1, 4) { Rect(1, h) => h, _ => 0 }"#, Ok(Value::Number(4)));
+test!(match_no_arm, r#"match 5 { 1 => 10, 2 => 20 }"#, Err(RuntimeError::NoMatchingArm));
+test!(match_non_exhaustive, r#"enum Shape { Circle(r), Rect(w, h) }
+fn area(s) { return match s { Circle(r) => r }; }
+1"#, Err(RuntimeError::NonExhaustiveMatch));
+test!(match_literal_field_not_exhaustive, r#"enum Shape { Circle(r), Rect(w, h) } match Circle(1) { Circle(1) => 1, Rect(w, h) => 2 }"#, Err(RuntimeError::NonExhaustiveMatch));
+test!(variant_equality, r#"enum Shape { Circle(r), Rect(w, h) } Circle(1) == Circle(1)"#, Ok(Value::Bool(true)));
+test!(variant_wrong_fields, r#"enum Shape { Circle(r), Rect(w, h) } Rect(1)"#, Err(RuntimeError::ArityMismatch { name: "Rect".to_string(), expected: 2, found: 1 }));
+
+// Destructuring let
+test!(let_list_pattern, r#"let pair = [1, 2]; let [a, b] = pair; a * 10 + b"#, Ok(Value::Number(12)));
+test!(let_nested_list_pattern, r#"let [a, [b, _]] = [1, [2, 3]]; a + b"#, Ok(Value::Number(3)));
+test!(let_struct_pattern, r#"struct Point { x, y } let p = Point { x: 3, y: 4 }; let Point { x, y } = p; x * y"#, Ok(Value::Number(12)));
+test!(let_struct_pattern_rename, r#"struct Point { x, y } let Point { x: px, y } = Point { x: 3, y: 4 }; px + y"#, Ok(Value::Number(7)));
+test!(let_list_pattern_wrong_length, r#"let [a, b] = [1, 2, 3];"#, Err(RuntimeError::PatternMismatch));
+test!(let_struct_pattern_wrong_type, r#"struct Point { x, y } let Point { x, y } = [1, 2];"#, Err(RuntimeError::PatternMismatch));
+test!(let_struct_pattern_unknown_field, r#"struct Point { x, y } fn f(p) { let Point { z } = p; return z; } 1"#, Err(RuntimeError::UnknownField { name: "z".to_string() }));
+test!(match_list_pattern, r#"match [1, 2] { [x] => x, [x, y] => x + y, _ => 0 }"#, Ok(Value::Number(3)));
+
+// Tuples
+test!(tuple_literal, r#"(1, true)"#, Ok(Value::Tuple(vec![Value::Number(1), Value::Bool(true)])));
+test!(tuple_single, r#"(1,)"#, Ok(Value::Tuple(vec![Value::Number(1)])));
+test!(tuple_parenthetical_is_not_tuple, r#"(1 + 2)"#, Ok(Value::Number(3)));
+test!(tuple_index, r#"let t = (10, (20, 30)); t.0 + t.1.1"#, Ok(Value::Number(40)));
+test!(tuple_index_out_of_bounds, r#"let t = (1, 2); t.2"#, Err(RuntimeError::IndexOutOfBounds { index: 2, len: 2 }));
+test!(tuple_multiple_return, r#"fn divmod(a, b) {
+  let q = a / b;
+  return (q, a - q * b);
+}
+let (q, r) = divmod(17, 5);
+q * 10 + r"#, Ok(Value::Number(32)));
+test!(tuple_pattern_mismatch, r#"let (a, b) = (1, 2, 3);"#, Err(RuntimeError::PatternMismatch));
+test!(tuple_equality, r#"(1, 2) == (1, 2)"#, Ok(Value::Bool(true)));
+test!(tuple_match, r#"match (0, 5) { (0, y) => y, (x, _) => x }"#, Ok(Value::Number(5)));
+
+// Floats
+test!(float_literal, r#"1.5"#, Ok(Value::Float(1.5)));
+test!(float_exponent, r#"2.5e-3 * 1e3"#, Ok(Value::Float(2.5)));
+test!(float_arithmetic, r#"1.5 + 2.25"#, Ok(Value::Float(3.75)));
+test!(float_mixed_promotes, r#"6 / 4.0"#, Ok(Value::Float(1.5)));
+test!(float_integer_division_unchanged, r#"6 / 4"#, Ok(Value::Number(1)));
+test!(float_power, r#"4 ^ 0.5"#, Ok(Value::Float(2.0)));
+test!(float_comparison, r#"1.5 < 2"#, Ok(Value::Bool(true)));
+test!(float_mixed_equality, r#"2.0 == 2"#, Ok(Value::Bool(true)));
+test!(greater_or_equal, r#"2 >= 2"#, Ok(Value::Bool(true)));
+test!(float_to_int, r#"int(2.9) + int(0 - 2.9)"#, Ok(Value::Number(0)));
+test!(int_to_float, r#"float(3)"#, Ok(Value::Float(3.0)));
+test!(float_to_int_out_of_range, r#"int(1e308 * 10)"#, Err(RuntimeError::NumberOutOfRange));
+
+#[test]
+fn float_display() {
+  assert_eq!(Value::Float(2.0).to_string(), "2.0");
+  assert_eq!(Value::Float(0.25).to_string(), "0.25");
+}
+
+// 64-bit and arbitrary-precision integers
+test!(integer_64_bit, r#"2 ^ 40"#, Ok(Value::Number(1099511627776)));
+test!(integer_promotes_on_overflow, r#"9223372036854775807 + 1 == 9223372036854775808"#, Ok(Value::Bool(true)));
+test!(integer_demotes_when_it_fits, r#"9223372036854775807 + 1 - 1"#, Ok(Value::Number(i64::MAX)));
+test!(big_literal_round_trip, r#"123456789012345678901234567890 - 123456789012345678901234567889"#, Ok(Value::Number(1)));
+test!(big_factorial, r#"let result = 1;
+for n in [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30] {
+  result = result * n;
+}
+result == 265252859812191058636308480000000"#, Ok(Value::Bool(true)));
+test!(big_division, r#"(2 ^ 100) / (2 ^ 98)"#, Ok(Value::Number(4)));
+test!(big_comparison, r#"2 ^ 70 > 2 ^ 69"#, Ok(Value::Bool(true)));
+test!(big_negative, r#"(0 - 2) ^ 63 - 1 < 0 - 9223372036854775808"#, Ok(Value::Bool(true)));
+test!(big_float_to_int, r#"int(1e20) == 100000000000000000000"#, Ok(Value::Bool(true)));
+
+#[test]
+fn big_display() -> Result<(), String> {
+  let (_, p) = program(r#"2 ^ 100"#).map_err(|e| format!("{:?}", e))?;
+  assert_eq!(start_interpreter(&p).map(|v| v.to_string()), Ok("1267650600228229401496703205376".to_string()));
+  let (_, p) = program(r#"0 - 3 ^ 50"#).map_err(|e| format!("{:?}", e))?;
+  assert_eq!(start_interpreter(&p).map(|v| v.to_string()), Ok("-717897987691852588770249".to_string()));
+  Ok(())
+}
+
+// Checked arithmetic
+test!(division_by_zero, r#"let x = 5; x / 0"#, Err(RuntimeError::DivisionByZero));
+test!(division_by_zero_float, r#"1.5 / 0"#, Err(RuntimeError::DivisionByZero));
+test!(division_by_zero_big, r#"(2 ^ 80) / 0"#, Err(RuntimeError::DivisionByZero));
+test!(division_by_zero_in_function, r#"fn f(a) { return 10 / a; } f(0)"#, Err(RuntimeError::DivisionByZero));
+test!(negative_exponent, r#"2 ^ (0 - 1)"#, Err(RuntimeError::NegativeExponent));
+test!(float_negative_exponent, r#"2.0 ^ (0 - 1)"#, Ok(Value::Float(0.5)));
+test!(exponent_overflow, r#"2 ^ 9223372036854775807"#, Err(RuntimeError::IntegerOverflow));
+test!(huge_exponent_of_one, r#"(0 - 1) ^ 9223372036854775807"#, Ok(Value::Number(-1)));
+test!(zero_to_the_zero, r#"0 ^ 0"#, Ok(Value::Number(1)));
+test!(min_divided_by_minus_one, r#"(0 - 9223372036854775807 - 1) / (0 - 1) == 9223372036854775808"#, Ok(Value::Bool(true)));
+
+// Modulo, floor division and bitwise operators
+test!(modulo, r#"17 % 5"#, Ok(Value::Number(2)));
+test!(modulo_negative_dividend, r#"(0 - 7) % 3"#, Ok(Value::Number(2)));
+test!(modulo_negative_divisor, r#"7 % (0 - 3)"#, Ok(Value::Number(-2)));
+test!(modulo_float, r#"(0 - 7.5) % 2"#, Ok(Value::Float(0.5)));
+test!(modulo_by_zero, r#"7 % 0"#, Err(RuntimeError::DivisionByZero));
+test!(floor_division, r#"7 // 2"#, Ok(Value::Number(3)));
+test!(floor_division_negative, r#"(0 - 7) // 2"#, Ok(Value::Number(-4)));
+test!(floor_division_float, r#"7.5 // 2"#, Ok(Value::Float(3.0)));
+test!(floor_division_big, r#"(0 - 2 ^ 70) // 3 == 0 - 393530540239137101142"#, Ok(Value::Bool(true)));
+test!(division_left_associative, r#"20 / 2 / 5"#, Ok(Value::Number(2)));
+test!(bitwise_and_or, r#"(12 & 10) + (12 | 10)"#, Ok(Value::Number(22)));
+test!(bitwise_negative, r#"(0 - 1) & 255"#, Ok(Value::Number(255)));
+test!(bitwise_not, r#"~5"#, Ok(Value::Number(-6)));
+test!(shift_left, r#"3 << 4"#, Ok(Value::Number(48)));
+test!(shift_left_promotes, r#"1 << 64 == 2 ^ 64"#, Ok(Value::Bool(true)));
+test!(shift_right_negative, r#"(0 - 9) >> 1"#, Ok(Value::Number(-5)));
+test!(shift_right_big, r#"(2 ^ 70) >> 68"#, Ok(Value::Number(4)));
+test!(negative_shift, r#"1 << (0 - 1)"#, Err(RuntimeError::NegativeShift));
+test!(bitwise_big, r#"(2 ^ 70) & 1"#, Err(RuntimeError::IntegerTooLarge { op: "&".to_string() }));
+test!(bitwise_float, r#"1.5 | 1"#, Err(RuntimeError::InvalidOperand { op: "|".to_string(), found: "float" }));
+test!(operator_precedence, r#"1 + 2 << 3 | 1 & ~0"#, Ok(Value::Number(25)));
+test!(closure_with_bitwise_or, r#"let f = |a, b| a | b; f(4, 1)"#, Ok(Value::Number(5)));
+
+// String literals
+test!(string_punctuation, r#""hello, world!""#, Ok(Value::String("hello, world!".to_string())));
+test!(string_empty, r#""""#, Ok(Value::String("".to_string())));
+test!(string_escapes, r#""a\nb\t\"c\" \\ \0""#, Ok(Value::String("a\nb\t\"c\" \\ \0".to_string())));
+test!(string_unicode, r#""caf\u{e9} \u{1F600} ü""#, Ok(Value::String("café 😀 ü".to_string())));
+test!(string_raw, r#"r"C:\temp\new""#, Ok(Value::String("C:\\temp\\new".to_string())));
+test!(string_raw_hashes, r##"r#"say "hi""#"##, Ok(Value::String("say \"hi\"".to_string())));
+test!(string_multi_line, "\"one\ntwo\"", Ok(Value::String("one\ntwo".to_string())));
+test!(string_line_continuation, "\"one \\\n    two\"", Ok(Value::String("one two".to_string())));
+test!(string_pattern, r#"match "a\tb" { "a\tb" => 1, _ => 2 }"#, Ok(Value::Number(1)));
+
+#[test]
+fn string_invalid_escape() {
+  assert!(program(r#""bad \q""#).is_err());
+  assert!(program(r#""\u{110000}""#).is_err());
+}
+
+// String operations
+test!(string_concatenation, r#"let name = "world"; "hello, " + name + "!""#, Ok(Value::String("hello, world!".to_string())));
+test!(string_equality, r#""abc" == "abc""#, Ok(Value::Bool(true)));
+test!(string_inequality, r#""abc" != "abd""#, Ok(Value::Bool(true)));
+test!(string_ordering, r#""apple" < "banana""#, Ok(Value::Bool(true)));
+test!(string_plus_number, r#""a" + 1"#, Err(RuntimeError::TypeMismatch { op: "+".to_string(), lhs: "string", rhs: "int" }));
+test!(string_len, r#"len("héllo")"#, Ok(Value::Number(5)));
+test!(string_substring, r#"substring("héllo", 1, 3)"#, Ok(Value::String("él".to_string())));
+test!(string_substring_out_of_bounds, r#"substring("abc", 2, 4)"#, Err(RuntimeError::IndexOutOfBounds { index: 4, len: 3 }));
+test!(string_split_join, r#"join(split("a,b,,c", ","), "-")"#, Ok(Value::String("a-b--c".to_string())));
+test!(string_split_characters, r#"len(split("abc", ""))"#, Ok(Value::Number(3)));
+test!(string_join_non_string, r#"join(["a", 1], ",")"#, Err(RuntimeError::ExpectedString { found: "int" }));
+test!(string_trim, r#"trim("  padded\n")"#, Ok(Value::String("padded".to_string())));
+test!(string_contains, r#"contains("haystack", "st")"#, Ok(Value::Bool(true)));
+test!(string_replace, r#"replace("a-b-c", "-", "+")"#, Ok(Value::String("a+b+c".to_string())));
+test!(string_case, r#"upper("abc") + lower("DEF")"#, Ok(Value::String("ABCdef".to_string())));
+test!(string_to_string, r#"to_string(42) + to_string(1.5) + to_string([1, "a"])"#, Ok(Value::String("421.5[1, \"a\"]".to_string())));
+test!(string_parse_int, r#"parse_int(" -42 ") + 2"#, Ok(Value::Number(-40)));
+test!(string_parse_int_big, r#"parse_int("99999999999999999999") == 99999999999999999999"#, Ok(Value::Bool(true)));
+test!(string_parse_int_invalid, r#"parse_int("4x")"#, Err(RuntimeError::InvalidInteger { text: "4x".to_string() }));
+test!(underscore_names, r#"fn add_one(some_value) { return some_value + 1; } add_one(1)"#, Ok(Value::Number(2)));
+
+// String interpolation
+test!(interpolation, r#"let x = 3; let a = 1; let b = 2; "x = {x}, sum = {a + b}""#, Ok(Value::String("x = 3, sum = 3".to_string())));
+test!(interpolation_only_expression, r#""{ 40 + 2 }""#, Ok(Value::String("42".to_string())));
+test!(interpolation_nested_string, r#""{upper("hi")}, {"there"}""#, Ok(Value::String("HI, there".to_string())));
+test!(interpolation_values, r#""{[1, "a"]} {1.0} {true}""#, Ok(Value::String("[1, \"a\"] 1.0 true".to_string())));
+test!(interpolation_escaped_braces, r#""\{x\}""#, Ok(Value::String("{x}".to_string())));
+test!(interpolation_not_in_raw_string, r#"r"{x}""#, Ok(Value::String("{x}".to_string())));
+test!(interpolation_error, r#""{missing}""#, Err(RuntimeError::UndefinedVariable { name: "missing".to_string() }));
+
+#[test]
+fn interpolation_syntax_error() {
+  assert!(program(r#""{}""#).is_err());
+  assert!(program(r#""{1 +}""#).is_err());
+}
+
+// Unit and Option
+test!(unit_let, r#"let x = 1;"#, Ok(Value::Unit));
+test!(unit_assignment, r#"let x = 1; x = 2"#, Ok(Value::Unit));
+test!(unit_for_loop, r#"for x in [1, 2] { x }"#, Ok(Value::Unit));
+test!(unit_function_ending_in_let, r#"fn f() { let y = 1; } f()"#, Ok(Value::Unit));
+test!(unit_equality, r#"fn f() { let y = 1; } f() == f()"#, Ok(Value::Bool(true)));
+test!(option_some, r#"Some(3)"#, Ok(Value::some(Value::Number(3))));
+test!(option_none, r#"None"#, Ok(Value::none()));
+test!(option_match, r#"let o = Some(3); match o { Some(v) => v * 2, None => 0 }"#, Ok(Value::Number(6)));
+test!(option_match_exhaustive, r#"match None { Some(v) => v }"#, Err(RuntimeError::NonExhaustiveMatch));
+test!(option_is_some, r#"is_some(Some(1)) == is_none(None)"#, Ok(Value::Bool(true)));
+test!(option_unwrap, r#"unwrap(Some(1)) + unwrap_or(None, 7)"#, Ok(Value::Number(8)));
+test!(option_unwrap_none, r#"unwrap(None)"#, Err(RuntimeError::UnwrapNone));
+test!(option_unwrap_not_option, r#"unwrap(1)"#, Err(RuntimeError::InvalidArguments { name: "unwrap".to_string() }));
+
+#[test]
+fn unit_display() {
+  assert_eq!(Value::Unit.to_string(), "()");
+  assert_eq!(Value::some(Value::String("a".to_string())).to_string(), "Some(\"a\")");
+}
+
+// Runtime errors
+test!(error_type_mismatch_names_types, r#""a" - 1.5"#, Err(RuntimeError::TypeMismatch { op: "-".to_string(), lhs: "string", rhs: "float" }));
+test!(error_not_iterable, r#"for x in 5 { x }"#, Err(RuntimeError::NotIterable { found: "int" }));
+test!(error_not_indexable, r#"let b = true; b[0]"#, Err(RuntimeError::NotIndexable { found: "bool" }));
+test!(error_field_of_non_struct, r#"let n = 1; n.x"#, Err(RuntimeError::InvalidFieldAccess { field: "x".to_string(), found: "int" }));
+
+#[test]
+fn error_messages() {
+  let message = |source| start_interpreter(&program(source).unwrap().1).unwrap_err().to_string();
+  assert_eq!(message(r#"x"#), "Undefined variable `x`");
+  assert_eq!(message(r#""a" + true"#), "Cannot apply `+` to string and bool");
+  assert_eq!(message(r#"let xs = [1]; xs[3]"#), "Index 3 out of bounds for length 1");
+  assert_eq!(message(r#"10 / 0"#), "Division by zero");
+  assert_eq!(message(r#"throw "boom";"#), "Uncaught exception: boom");
+}
+
+#[test]
+fn error_location() {
+  let location = |source| {
+    let error = start_interpreter(&program(source).unwrap().1).unwrap_err();
+    error.span.map(|span| span.line_col(source))
+  };
+  // The call is the most precise location for an undefined function.
+  assert_eq!(location("let a = 1;\nlet b = a + foo(2);"), Some((2, 13)));
+  // Otherwise the error points at the statement that raised it.
+  assert_eq!(location("let a = 1;\nlet b = a / 0;"), Some((2, 1)));
+  assert_eq!(location("fn f(x) {\n  return x / 0;\n}\nf(1)"), Some((2, 3)));
+  assert_eq!(location("struct P { x }\nlet p = 1;\nlet q = P { y: 2 };"), Some((3, 1)));
+}
+
+#[test]
+fn error_backtrace() {
+  let trace = |source: &str| {
+    let error = start_interpreter(&program(source).unwrap().1).unwrap_err();
+    error.trace.iter().map(|frame| {
+      let (line, column) = frame.span.line_col(source);
+      format!("{} {}:{}", frame.function, line, column)
+    }).collect::<Vec<_>>()
+  };
+  // Each function is reported where it was when the error happened; `main` has no caller.
+  assert_eq!(trace("fn bar(x) {\n  return x / 0;\n}\nfn foo(x) {\n  let y = bar(x);\n  return y;\n}\nfn main() {\n  let y = foo(1);\n  return y;\n}"),
+    vec!["bar 2:3", "foo 5:11", "main 9:11"]);
+  // A tail call replaces its caller, which keeps only the site of the last tail call.
+  assert_eq!(trace("fn bar(x) {\n  return x / 0;\n}\nfn foo(x) {\n  return bar(x);\n}\nfn main() {\n  return foo(1);\n}"),
+    vec!["bar 2:3", "foo 5:10"]);
+  // Without `main`, the outermost frame is the script itself.
+  assert_eq!(trace("fn f(x) {\n  return unwrap(x);\n}\nlet a = f(None);"), vec!["f 2:10", "<script> 4:9"]);
+  assert_eq!(trace("let g = |x| x / 0;\ng(1)"), vec!["<anonymous> 1:13", "<script> 2:1"]);
+  assert_eq!(trace("let a = 1 / 0;"), vec!["<script> 1:1"]);
+}
+
+#[test]
+fn error_report() {
+  let source = "fn f(x) {\n  return x / 0;\n}\nf(1)";
+  let error = start_interpreter(&program(source).unwrap().1).unwrap_err();
+  assert_eq!(error.report("script.asa", source), "error: Division by zero\n  at f (script.asa:2:3)\n  at <script> (script.asa:4:1)");
+}
+
+#[test]
+fn error_report_repeated_frames() {
+  let source = "fn f(n) {\n  return 1 + f(n + 1);\n}\nf(0)";
+  let error = Runtime::new().with_max_call_depth(4).start(&program(source).unwrap().1).unwrap_err();
+  assert_eq!(error.report("script.asa", source),
+    "error: Stack overflow: call to `f` with 4 calls already running\n  at f (script.asa:2:14)\n  ... repeated 3 more times\n  at <script> (script.asa:4:1)");
+}
+
+#[test]
+fn error_report_tail_calls() {
+  let source = "fn bar(x) {\n  return x / 0;\n}\nfn baz(x) {\n  return bar(x);\n}\nfn foo(x) {\n  return baz(x);\n}\nlet y = foo(1);";
+  let error = start_interpreter(&program(source).unwrap().1).unwrap_err();
+  assert_eq!(error.report("script.asa", source),
+    "error: Division by zero\n  at bar (script.asa:2:3)\n  at baz (script.asa:5:10)\n  ... 1 more frame replaced by tail calls\n  at <script> (script.asa:10:9)");
+}
+
+// Error handling
+test!(try_no_error, r#"try { 1 } catch (e) { 2 }"#, Ok(Value::Number(1)));
+test!(try_catch_division_by_zero, r#"try { 1 / 0 } catch (e) { e }"#, Ok(Value::String("Division by zero".to_string())));
+test!(try_catch_index_out_of_bounds, r#"let xs = [1]; try { xs[5] } catch (e) { e }"#, Ok(Value::String("Index 5 out of bounds for length 1".to_string())));
+test!(try_catch_thrown_value, r#"try { throw 42; } catch (e) { e + 1 }"#, Ok(Value::Number(43)));
+test!(try_catch_from_nested_call, r#"fn g(x) { throw Some(x); } fn f(x) { return g(x); } try { f(7) } catch (e) { unwrap(e) }"#, Ok(Value::Number(7)));
+test!(try_catch_continues_after, r#"let x = 0; try { x = 1; 1 / 0; x = 2; } catch (e) { x = x + 10; } x"#, Ok(Value::Number(11)));
+test!(try_catch_rethrow, r#"try { try { throw 1; } catch (e) { throw e + 1; } } catch (e) { e }"#, Ok(Value::Number(2)));
+test!(try_catch_return, "fn safe(a, b) {\n  try {\n    return a / b;\n  } catch (e) {\n    return 0;\n  }\n}\nsafe(1, 0) + safe(6, 3)", Ok(Value::Number(2)));
+test!(try_catch_stack_restored, r#"fn f(x) { let y = 1; return x / 0; } let y = 5; try { f(1) } catch (e) { y }"#, Ok(Value::Number(5)));
+test!(throw_uncaught, r#"throw "boom";"#, Err(RuntimeError::Thrown { value: Value::String("boom".to_string()) }));
+
+// Arity, default parameters and named arguments
+test!(arity_too_few, r#"fn f(a, b, c) { return a; } f(1, 2)"#, Err(RuntimeError::ArityMismatch { name: "f".to_string(), expected: 3, found: 2 }));
+test!(arity_too_many, r#"fn f(a, b) { return a; } f(1, 2, 3)"#, Err(RuntimeError::ArityMismatch { name: "f".to_string(), expected: 2, found: 3 }));
+test!(arity_no_parameters, r#"fn g() { return 1; } g(2)"#, Err(RuntimeError::ArityMismatch { name: "g".to_string(), expected: 0, found: 1 }));
+test!(arity_checked_before_running, r#"fn f(a) { return a; } let x = 1 / 0; f()"#, Err(RuntimeError::ArityMismatch { name: "f".to_string(), expected: 1, found: 0 }));
+test!(arity_closure, r#"let g = |a, b| a + b; g(1)"#, Err(RuntimeError::ArityMismatch { name: "<anonymous>".to_string(), expected: 2, found: 1 }));
+test!(arity_function_value, r#"fn f(a) { return a; } let h = f; h(1, 2)"#, Err(RuntimeError::ArityMismatch { name: "f".to_string(), expected: 1, found: 2 }));
+test!(arity_parameter_shadows_function, r#"fn apply(f, x) { return f(x); } fn f(a, b) { return a; } apply(|y| y + 1, 2)"#, Ok(Value::Number(3)));
+test!(default_parameter, r#"fn connect(host, port = 80) { return host + port; } connect(1) + connect(1, 8000)"#, Ok(Value::Number(8082)));
+test!(default_parameter_uses_earlier, r#"fn f(a, b = a * 2) { return b; } f(5)"#, Ok(Value::Number(10)));
+test!(default_parameter_function_literal, r#"let g = fn(a, b = 10) { return a + b; }; g(1)"#, Ok(Value::Number(11)));
+test!(named_argument, r#"fn connect(host, port = 80) { return port; } connect(1, port: 8000)"#, Ok(Value::Number(8000)));
+test!(named_arguments_any_order, r#"fn f(a, b) { return a - b; } f(b: 1, a: 10)"#, Ok(Value::Number(9)));
+test!(named_argument_missing_required, r#"fn connect(host, port = 80) { return port; } connect(port: 1)"#, Err(RuntimeError::MissingArgument { function: "connect".to_string(), name: "host".to_string() }));
+test!(named_argument_unknown, r#"fn f(a) { return a; } f(b: 1)"#, Err(RuntimeError::UnknownArgument { function: "f".to_string(), name: "b".to_string() }));
+test!(named_argument_duplicate, r#"fn f(a) { return a; } f(1, a: 2)"#, Err(RuntimeError::DuplicateArgument { function: "f".to_string(), name: "a".to_string() }));
+test!(named_argument_builtin, r#"unwrap(x: Some(1))"#, Err(RuntimeError::UnknownArgument { function: "unwrap".to_string(), name: "x".to_string() }));
+
+// Variadic functions and spreading
+test!(rest_parameter, r#"fn sum(...xs) { let total = 0; for x in xs { total = total + x; } return total; } sum(1, 2, 3)"#, Ok(Value::Number(6)));
+test!(rest_parameter_empty, r#"fn sum(...xs) { return len(xs); } sum()"#, Ok(Value::Number(0)));
+test!(rest_parameter_after_fixed, r#"fn f(a, ...rest) { return rest; } f(1, 2, 3)"#, Ok(Value::list(vec![Value::Number(2), Value::Number(3)])));
+test!(rest_parameter_function_literal, r#"let g = fn(...xs) { return len(xs); }; g(1, 2)"#, Ok(Value::Number(2)));
+test!(rest_parameter_not_named, r#"fn f(a, ...r) { return len(r); } f(1, r: 2)"#, Err(RuntimeError::UnknownArgument { function: "f".to_string(), name: "r".to_string() }));
+test!(spread_arguments, r#"fn f(a, b, c) { return a - b - c; } let xs = [2, 3]; f(10, ...xs)"#, Ok(Value::Number(5)));
+test!(spread_into_rest_parameter, r#"fn count(...xs) { return len(xs); } count(0, ...[1, 2], ...[3])"#, Ok(Value::Number(4)));
+test!(spread_into_builtin, r#"len(...[[1, 2]])"#, Ok(Value::Number(2)));
+test!(spread_too_many, r#"fn f(a, b) { return a; } f(...[1, 2, 3])"#, Err(RuntimeError::ArityMismatch { name: "f".to_string(), expected: 2, found: 3 }));
+test!(spread_not_a_list, r#"fn f(a) { return a; } f(...5)"#, Err(RuntimeError::NotIterable { found: "int" }));
+
+#[test]
+fn rest_parameter_must_be_last() {
+  let parsed = program(r#"fn f(...xs, a) { return 1; }"#);
+  assert!(!matches!(parsed, Ok(("", _))));
+}
+
+#[test]
+fn default_parameters_must_be_last() {
+  let sources = [
+    r#"fn f(a = 1, b) { return b; } f(2)"#,
+    r#"f(2); fn f(a = 1, b) { return b; }"#,
+    r#"let f = fn(a = 1, b) { return b; }; f(2)"#,
+    r#"fn f(a = 1, b, c = 2) { return b; }"#,
+  ];
+  for source in sources {
+    let (rest, p) = program(source).unwrap();
+    assert_eq!(rest, "");
+    let error = start_interpreter(&p).unwrap_err();
+    assert_eq!(error.error.to_string(), "Required parameter `b` follows a parameter with a default value");
+  }
+}
+
+// Function calls
+test!(call_arguments_before_lookup, r#"let f = |x| x + 1; fn g() { f = |x| x * 10; return 5; } f(g())"#, Ok(Value::Number(50)));
+test!(call_undefined_before_arguments, r#"foo(1 / 0)"#, Err(RuntimeError::UndefinedFunction { name: "foo".to_string() }));
+test!(call_function_many_times, r#"fn inc(x) { return x + 1; } let n = 0; for i in [1, 2, 3, 4, 5, 6, 7, 8] { n = inc(n); } n"#, Ok(Value::Number(8)));
+
+// Calling convention
+test!(call_nested, r#"fn double(x) { return x * 2; } fn inc(x) { return x + 1; } let x = 5; double(inc(x))"#, Ok(Value::Number(12)));
+test!(call_nested_same_function, r#"fn f(x) { let y = x + 1; return y; } let y = 100; let x = 1; f(f(f(x))) + y"#, Ok(Value::Number(104)));
+test!(call_argument_in_caller_scope, r#"fn f(x, y) { return x - y; } fn g(y) { return f(y, 1); } g(10)"#, Ok(Value::Number(9)));
+test!(call_argument_shadows_caller_name, r#"fn f(x) { return x * 10; } fn g(x) { return f(x + 1) + x; } g(2)"#, Ok(Value::Number(32)));
+test!(call_callee_cannot_see_caller_locals, r#"fn g() { return secret; } fn f() { let secret = 1; return g(); } f()"#, Err(RuntimeError::UndefinedVariable { name: "secret".to_string() }));
+test!(call_callee_frame_discarded, r#"fn f() { let local = 1; return local; } f(); local"#, Err(RuntimeError::UndefinedVariable { name: "local".to_string() }));
+test!(call_arguments_left_to_right, r#"let log = []; fn t(v) { push(log, v); return v; } fn f(a, b, c) { return a; } f(t(1), t(2), t(3)); log"#, Ok(Value::list(vec![Value::Number(1), Value::Number(2), Value::Number(3)])));
+test!(call_named_arguments_in_written_order, r#"let log = []; fn t(v) { push(log, v); return v; } fn f(a, b, c) { return a; } f(c: t(1), a: t(2), b: t(3)); log"#, Ok(Value::list(vec![Value::Number(1), Value::Number(2), Value::Number(3)])));
+test!(call_default_in_callee_scope, r#"fn f(a, b = a) { return b; } let a = 99; f(1)"#, Ok(Value::Number(1)));
+test!(call_recursive, r#"fn sum(n) { return match n { 0 => 0, _ => n + sum(n - 1) }; } sum(5)"#, Ok(Value::Number(15)));
+test!(call_recursive_locals_per_frame, r#"fn f(n) { let x = n; let r = match n { 0 => 0, _ => f(n - 1) }; return x + r; } let x = 7; f(3) + x"#, Ok(Value::Number(13)));
+test!(call_mutually_recursive, r#"fn even(n) { return match n { 0 => true, _ => odd(n - 1) }; } fn odd(n) { return match n { 0 => false, _ => even(n - 1) }; } even(4)"#, Ok(Value::Bool(true)));
+
+// Call depth
+fn run_with_max_call_depth(source: &str, depth: usize) -> Result<Value, RuntimeError> {
+  let (_, p) = program(source).unwrap();
+  Runtime::new().with_max_call_depth(depth).start(&p).map_err(|error| error.error)
+}
+
+#[test]
+fn call_depth_limit() {
+  let sum = "fn sum(n) { return match n { 0 => 0, _ => n + sum(n - 1) }; } sum(COUNT)";
+  // `sum(4)` makes five nested calls, which is just within the limit.
+  assert_eq!(run_with_max_call_depth(&sum.replace("COUNT", "4"), 5), Ok(Value::Number(10)));
+  assert_eq!(run_with_max_call_depth(&sum.replace("COUNT", "5"), 5), Err(RuntimeError::StackOverflow { function: "sum".to_string(), depth: 5 }));
+  // `main` counts as a call, unless it returns the call directly and is replaced by it.
+  assert_eq!(run_with_max_call_depth(&format!("{} fn main() {{ let total = sum(4); return total; }}", sum.replace("COUNT", "0")), 5),
+    Err(RuntimeError::StackOverflow { function: "sum".to_string(), depth: 5 }));
+  assert_eq!(run_with_max_call_depth(&format!("{} fn main() {{ return sum(4); }}", sum.replace("COUNT", "0")), 5), Ok(Value::Number(10)));
+}
+
+#[test]
+fn call_depth_mutual_recursion() {
+  let source = "fn even(n) { return match n { 0 => true, _ => odd(n - 1) == true }; } fn odd(n) { return match n { 0 => false, _ => even(n - 1) == true }; } even(6)";
+  assert_eq!(run_with_max_call_depth(source, 3), Err(RuntimeError::StackOverflow { function: "odd".to_string(), depth: 3 }));
+  assert_eq!(run_with_max_call_depth(source, 7), Ok(Value::Bool(true)));
+  // In tail position the calls replace each other, so any limit is enough.
+  let source = "fn even(n) { return match n { 0 => true, _ => odd(n - 1) }; } fn odd(n) { return match n { 0 => false, _ => even(n - 1) }; } even(6)";
+  assert_eq!(run_with_max_call_depth(source, 1), Ok(Value::Bool(true)));
+}
+
+#[test]
+fn call_depth_overflow_caught() {
+  let source = "fn f(n) { return 1 + f(n + 1); } try { f(0) } catch (e) { e }";
+  assert_eq!(run_with_max_call_depth(source, 3), Ok(Value::String("Stack overflow: call to `f` with 3 calls already running".to_string())));
+}
+
+#[test]
+fn call_depth_default_limit() {
+  // The default limits fit the stack of an ordinary thread, so runaway recursion stops at the default depth.
+  let error = std::thread::spawn(|| {
+    let (_, p) = program("fn f(n) { return 1 + f(n + 1); } f(0)").unwrap();
+    start_interpreter(&p).unwrap_err().error.to_string()
+  }).join().unwrap();
+  assert_eq!(error, RuntimeError::StackOverflow { function: "f".to_string(), depth: DEFAULT_MAX_CALL_DEPTH }.to_string());
+}
+
+#[test]
+fn call_stack_size_default_limit() {
+  // Past the default depth the stack budget still stops the recursion before the thread's stack runs out.
+  let error = std::thread::spawn(|| {
+    let (_, p) = program("fn f(n) { return 1 + f(n + 1); } f(0)").unwrap();
+    Runtime::new().with_max_call_depth(usize::MAX).start(&p).unwrap_err().error.to_string()
+  }).join().unwrap();
+  assert_eq!(error, RuntimeError::StackExhausted { function: "f".to_string(), limit: DEFAULT_MAX_STACK_SIZE }.to_string());
+}
+
+#[test]
+fn call_stack_size_limit() {
+  let sum = "fn sum(n) { return match n { 0 => 0, _ => n + sum(n - 1) }; } sum(3)";
+  // The outermost call is measured from its own frame, so any nested call goes over a limit of zero.
+  let error = Runtime::new().with_max_stack_size(0).start(&program(sum).unwrap().1).unwrap_err();
+  assert_eq!(error.error, RuntimeError::StackExhausted { function: "sum".to_string(), limit: 0 });
+}
+
+// Tail calls
+test!(tail_call_accumulator, r#"fn sum(n, acc) { return match n { 0 => acc, _ => sum(n - 1, acc + n) }; } sum(100000, 0)"#, Ok(Value::Number(5000050000)));
+test!(tail_call_mutual_recursion, r#"fn even(n) { return match n { 0 => true, _ => odd(n - 1) }; } fn odd(n) { return match n { 0 => false, _ => even(n - 1) }; } even(100001)"#, Ok(Value::Bool(false)));
+test!(tail_call_function_value, r#"fn go(f, n) { return match n { 0 => "done", _ => f(f, n - 1) }; } go(go, 100000)"#, Ok(Value::String("done".to_string())));
+test!(tail_call_arguments_in_caller_scope, r#"fn f(x, y) { return x - y; } fn g(x) { let y = x * 2; return f(y, x); } g(3)"#, Ok(Value::Number(3)));
+test!(tail_call_arity, r#"fn f(a) { return a; } fn g() { let h = f; return h(1, 2); } g()"#, Err(RuntimeError::ArityMismatch { name: "f".to_string(), expected: 1, found: 2 }));
+test!(tail_call_builtin, r#"fn f(x) { return unwrap(x); } f(Some(5))"#, Ok(Value::Number(5)));
+test!(tail_call_in_try, r#"fn f(n) { throw n; } fn g(n) { try { return f(n); } catch (e) { return e + 1; } } g(1)"#, Ok(Value::Number(2)));
+
+#[test]
+fn tail_call_constant_space() {
+  let source = "fn sum(n, acc) { return match n { 0 => acc, _ => sum(n - 1, acc + n) }; } sum(100000, 0)";
+  // Far deeper than the call limit, so each call must replace the one before it.
+  assert_eq!(run_with_max_call_depth(source, 2), Ok(Value::Number(5000050000)));
+}
+
+#[test]
+fn tail_call_in_match_arm() {
+  let source = "fn count(n) { return match n { 0 => \"done\", _ => count(n - 1) }; } count(100000)";
+  assert_eq!(run_with_max_call_depth(source, 2), Ok(Value::String("done".to_string())));
+}
+
+#[test]
+fn tail_call_runaway_step_limit() {
+  // Tail calls never reach the depth limit, so only a step limit stops recursion that never ends.
+  let (_, p) = program("fn f(n) { return f(n); } f(0)").unwrap();
+  let error = Runtime::new().with_max_steps(10000).start(&p).unwrap_err();
+  assert_eq!(error.error, RuntimeError::StepLimitExceeded { limit: 10000 });
+  let (_, p) = program("fn sum(n, acc) { return match n { 0 => acc, _ => sum(n - 1, acc + n) }; } sum(100, 0)").unwrap();
+  assert_eq!(Runtime::new().with_max_steps(10000).start(&p), Ok(Value::Number(5050)));
+}
+
+#[test]
+fn tail_call_in_try_keeps_caller() {
+  // The caller's `catch` has to stay in place, so a call returned from inside `try` still nests.
+  let source = "fn f(n) { try { return match n { 0 => \"done\", _ => f(n - 1) }; } catch (e) { return \"caught\"; } } f(10)";
+  assert_eq!(run_with_max_call_depth(source, 5), Ok(Value::String("caught".to_string())));
+  assert_eq!(run_with_max_call_depth(source, 11), Ok(Value::String("done".to_string())));
+}